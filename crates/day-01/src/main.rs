@@ -1,10 +1,123 @@
-use std::io;
+use std::{
+    collections::{HashSet, VecDeque},
+    io,
+    str::FromStr,
+};
 
 const BASE_10: u32 = 10;
-const DIGIT_WORDS: [&str; 9] = [
-    "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+const ENGLISH_DIGIT_WORDS: [(&str, u32); 9] = [
+    ("one", 1),
+    ("two", 2),
+    ("three", 3),
+    ("four", 4),
+    ("five", 5),
+    ("six", 6),
+    ("seven", 7),
+    ("eight", 8),
+    ("nine", 9),
 ];
-const MAX_DIGIT_WORD_LEN: usize = 5;
+
+/// A table of words that are decoded as digits, e.g. "one" -> 1.
+/// Words are tried in table order, so when two words end at the same character
+/// the one listed first wins.
+#[derive(Debug, PartialEq)]
+struct DigitVocabulary {
+    words: Vec<(String, u32)>,
+}
+impl DigitVocabulary {
+    fn new(words: Vec<(String, u32)>) -> Result<Self, String> {
+        let mut seen_words = HashSet::new();
+        for (word, digit) in &words {
+            if word.is_empty() {
+                return Err("Digit words must not be empty".to_string());
+            }
+            if *digit >= BASE_10 {
+                return Err(format!(
+                    "Digit for word \"{}\" must be between 0 and 9, got {}",
+                    word, digit
+                ));
+            }
+            if !seen_words.insert(word.as_str()) {
+                return Err(format!("Duplicate digit word \"{}\"", word));
+            }
+        }
+
+        Ok(Self { words })
+    }
+
+    fn english() -> Self {
+        Self::new(
+            ENGLISH_DIGIT_WORDS
+                .iter()
+                .map(|(word, digit)| (word.to_string(), *digit))
+                .collect(),
+        )
+        .unwrap()
+    }
+}
+impl FromStr for DigitVocabulary {
+    type Err = String;
+
+    /// Parses a vocabulary file with one `<word> <digit>` entry per line.
+    /// Blank lines and lines starting with `#` are ignored.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = Vec::new();
+        for (line_num, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (word, digit) = line.rsplit_once(char::is_whitespace).ok_or_else(|| {
+                format!(
+                    "LINE {}: Expected a word and a digit separated by whitespace",
+                    line_num
+                )
+            })?;
+            let digit = digit
+                .parse::<u32>()
+                .map_err(|e| format!("LINE {}: Failed to parse digit: {}", line_num, e))?;
+            words.push((word.trim_end().to_string(), digit));
+        }
+        Self::new(words)
+    }
+}
+
+/// Which tokens count as digits when decoding a calibration line.
+/// Part 1 only accepts ASCII digits, Part 2 also accepts spelled out digit words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecodeMode {
+    DigitsOnly,
+    DigitsAndWords,
+}
+impl DecodeMode {
+    fn label(&self) -> &'static str {
+        match self {
+            DecodeMode::DigitsOnly => "Part 1 (digits only)",
+            DecodeMode::DigitsAndWords => "Part 2 (digits and words)",
+        }
+    }
+
+    fn arg_name(&self) -> &'static str {
+        match self {
+            DecodeMode::DigitsOnly => "part1",
+            DecodeMode::DigitsAndWords => "part2",
+        }
+    }
+}
+impl FromStr for DecodeMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1" | "part1" | "digits" => Ok(DecodeMode::DigitsOnly),
+            "2" | "part2" | "words" => Ok(DecodeMode::DigitsAndWords),
+            _ => Err(format!(
+                "Unknown decode mode \"{}\", expected one of \"part1\" or \"part2\"",
+                s
+            )),
+        }
+    }
+}
 
 enum DigitsRead {
     NoDigits,
@@ -33,71 +146,866 @@ impl DigitsRead {
     }
 }
 
-fn get_two_digit_number_from_line(line_str: &str) -> Result<u32, String> {
+const NO_TRANSITION: usize = usize::MAX;
+const ROOT_STATE: usize = 0;
+
+/// Aho-Corasick automaton over a set of byte patterns.
+/// Failure links are folded into the transition table when it is built, so
+/// scanning costs one table lookup per byte no matter how many patterns there are.
+struct Automaton {
+    transitions: Vec<[usize; 256]>,
+    /// Indexes of every pattern that ends in each state, in ascending order
+    outputs: Vec<Vec<usize>>,
+}
+impl Automaton {
+    fn new(patterns: &[Vec<u8>]) -> Self {
+        let mut transitions = vec![[NO_TRANSITION; 256]];
+        let mut outputs = vec![vec![]];
+
+        for (pattern_idx, pattern) in patterns.iter().enumerate() {
+            let mut state = ROOT_STATE;
+            for &byte in pattern {
+                if transitions[state][byte as usize] == NO_TRANSITION {
+                    transitions.push([NO_TRANSITION; 256]);
+                    outputs.push(vec![]);
+                    transitions[state][byte as usize] = transitions.len() - 1;
+                }
+                state = transitions[state][byte as usize];
+            }
+            outputs[state].push(pattern_idx);
+        }
+
+        // Breadth first so a state's failure target is always finished before the state itself
+        let mut failure = vec![ROOT_STATE; transitions.len()];
+        let mut queue = VecDeque::new();
+        for transition in transitions[ROOT_STATE].iter_mut() {
+            match *transition {
+                NO_TRANSITION => *transition = ROOT_STATE,
+                next_state => queue.push_back(next_state),
+            }
+        }
+        while let Some(state) = queue.pop_front() {
+            let inherited_outputs = outputs[failure[state]].clone();
+            outputs[state].extend(inherited_outputs);
+            outputs[state].sort();
+
+            let fallback_transitions = transitions[failure[state]];
+            for (transition, fallback_state) in
+                transitions[state].iter_mut().zip(fallback_transitions)
+            {
+                match *transition {
+                    NO_TRANSITION => *transition = fallback_state,
+                    next_state => {
+                        failure[next_state] = fallback_state;
+                        queue.push_back(next_state);
+                    }
+                }
+            }
+        }
+
+        Self {
+            transitions,
+            outputs,
+        }
+    }
+
+    fn step(&self, state: usize, byte: u8) -> usize {
+        self.transitions[state][byte as usize]
+    }
+
+    fn outputs(&self, state: usize) -> &[usize] {
+        &self.outputs[state]
+    }
+}
+
+/// Finds the first and last digit in a line for a given mode and vocabulary.
+/// Built once and reused for every line. ASCII digits are always patterns and
+/// take priority over vocabulary words, followed by the words in vocabulary order.
+struct DigitMatcher {
+    patterns: Vec<(Vec<u8>, u32)>,
+    forward: Automaton,
+    /// Matches reversed patterns against the line read back to front
+    reverse: Automaton,
+    max_pattern_len: usize,
+}
+impl DigitMatcher {
+    fn new(vocabulary: &DigitVocabulary, mode: DecodeMode) -> Self {
+        let mut patterns: Vec<(Vec<u8>, u32)> = (0..BASE_10)
+            .map(|digit| {
+                (
+                    char::from_digit(digit, BASE_10)
+                        .unwrap()
+                        .to_string()
+                        .into_bytes(),
+                    digit,
+                )
+            })
+            .collect();
+        if mode == DecodeMode::DigitsAndWords {
+            patterns.extend(
+                vocabulary
+                    .words
+                    .iter()
+                    .map(|(word, digit)| (word.as_bytes().to_vec(), *digit)),
+            );
+        }
+
+        let forward_patterns: Vec<Vec<u8>> =
+            patterns.iter().map(|(bytes, _)| bytes.clone()).collect();
+        let reverse_patterns: Vec<Vec<u8>> = patterns
+            .iter()
+            .map(|(bytes, _)| bytes.iter().rev().copied().collect())
+            .collect();
+        let max_pattern_len = patterns.iter().map(|(bytes, _)| bytes.len()).max().unwrap();
+
+        Self {
+            forward: Automaton::new(&forward_patterns),
+            reverse: Automaton::new(&reverse_patterns),
+            patterns,
+            max_pattern_len,
+        }
+    }
+
+    /// Every digit or word match in the line, ordered by where the match ends.
+    /// Matches ending at the same byte are ordered by pattern priority.
+    fn find_all_matches(&self, line_str: &str) -> Vec<DigitMatch> {
+        let mut matches = vec![];
+        let mut state = ROOT_STATE;
+        for (byte_offset, &byte) in line_str.as_bytes().iter().enumerate() {
+            state = self.forward.step(state, byte);
+            for &pattern_idx in self.forward.outputs(state) {
+                let (pattern, digit) = &self.patterns[pattern_idx];
+                matches.push(DigitMatch {
+                    start: byte_offset + 1 - pattern.len(),
+                    end: byte_offset + 1,
+                    digit: *digit,
+                });
+            }
+        }
+        matches
+    }
+
+    /// The digit of the match that ends first, stopping as soon as one is found.
+    fn first_digit(&self, line_str: &str) -> Option<u32> {
+        let mut state = ROOT_STATE;
+        for &byte in line_str.as_bytes() {
+            state = self.forward.step(state, byte);
+            if let Some(&pattern_idx) = self.forward.outputs(state).first() {
+                return Some(self.patterns[pattern_idx].1);
+            }
+        }
+        None
+    }
+
+    /// The digit of the match that ends last, scanning from the back of the line.
+    /// A reversed match found first is not necessarily the one that ends last, so
+    /// after the first hit we keep scanning until no longer pattern could beat it.
+    fn last_digit(&self, line_str: &str) -> Option<u32> {
+        // (offset from the end of the line where the match ends, pattern index)
+        let mut best_match: Option<(usize, usize)> = None;
+
+        let mut state = ROOT_STATE;
+        for (reverse_offset, &byte) in line_str.as_bytes().iter().rev().enumerate() {
+            if let Some((best_end_offset, _)) = best_match {
+                if reverse_offset >= best_end_offset + self.max_pattern_len {
+                    break;
+                }
+            }
+
+            state = self.reverse.step(state, byte);
+            for &pattern_idx in self.reverse.outputs(state) {
+                let end_offset = reverse_offset + 1 - self.patterns[pattern_idx].0.len();
+                if best_match.is_none_or(|best| (end_offset, pattern_idx) < best) {
+                    best_match = Some((end_offset, pattern_idx));
+                }
+            }
+        }
+
+        best_match.map(|(_, pattern_idx)| self.patterns[pattern_idx].1)
+    }
+}
+
+fn get_two_digit_number_from_line(line_str: &str, matcher: &DigitMatcher) -> Result<u32, String> {
     let mut digits_read = DigitsRead::NoDigits;
-    for (i, c) in line_str.chars().enumerate() {
-        if c.is_digit(BASE_10) {
-            digits_read = digits_read.push_digit(c.to_digit(BASE_10).unwrap());
-            continue;
+    if let (Some(first_digit), Some(last_digit)) =
+        (matcher.first_digit(line_str), matcher.last_digit(line_str))
+    {
+        digits_read = digits_read.push_digit(first_digit).push_digit(last_digit);
+    }
+    digits_read.try_calculate_two_digit_number()
+}
+
+/// A digit or digit word found in a line, `start..end` being its byte span.
+#[derive(Debug, PartialEq)]
+struct DigitMatch {
+    start: usize,
+    end: usize,
+    digit: u32,
+}
+
+/// Every match found in a line, and which of them `DigitsRead` used as the first
+/// and last digit of the calibration value.
+#[derive(Debug, PartialEq)]
+struct LineTrace {
+    matches: Vec<DigitMatch>,
+    first_match: Option<usize>,
+    last_match: Option<usize>,
+    value: Result<u32, String>,
+}
+impl LineTrace {
+    fn new(line_str: &str, matcher: &DigitMatcher) -> Self {
+        let matches = matcher.find_all_matches(line_str);
+
+        // Only the highest priority match ending at each byte is pushed as a digit
+        let mut digits_read = DigitsRead::NoDigits;
+        let mut first_match = None;
+        let mut last_match = None;
+        let mut prev_end = None;
+        for (match_idx, digit_match) in matches.iter().enumerate() {
+            if prev_end == Some(digit_match.end) {
+                continue;
+            }
+            prev_end = Some(digit_match.end);
+
+            digits_read = digits_read.push_digit(digit_match.digit);
+            first_match = first_match.or(Some(match_idx));
+            last_match = Some(match_idx);
+        }
+
+        Self {
+            matches,
+            first_match,
+            last_match,
+            value: digits_read.try_calculate_two_digit_number(),
+        }
+    }
+
+    fn match_role(&self, match_idx: usize) -> &'static str {
+        match (
+            self.first_match == Some(match_idx),
+            self.last_match == Some(match_idx),
+        ) {
+            (true, true) => "first+last",
+            (true, false) => "first",
+            (false, true) => "last",
+            (false, false) => "",
+        }
+    }
+
+    fn format_table(&self, line_num: usize, line_str: &str) -> String {
+        let mut table = format!("LINE {}: {}\n", line_num, line_str);
+        table.push_str(&format!(
+            "  {:<10} {:<12} {:<5} {}\n",
+            "BYTES", "TEXT", "DIGIT", "ROLE"
+        ));
+        for (match_idx, digit_match) in self.matches.iter().enumerate() {
+            let row = format!(
+                "  {:<10} {:<12} {:<5} {}",
+                format!("{}..{}", digit_match.start, digit_match.end),
+                &line_str[digit_match.start..digit_match.end],
+                digit_match.digit,
+                self.match_role(match_idx),
+            );
+            table.push_str(row.trim_end());
+            table.push('\n');
+        }
+        match &self.value {
+            Ok(value) => table.push_str(&format!("  VALUE: {}\n", value)),
+            Err(e) => table.push_str(&format!("  ERROR: {}\n", e)),
         }
+        table
+    }
 
-        let slice_start = if i > MAX_DIGIT_WORD_LEN {
-            i - MAX_DIGIT_WORD_LEN
-        } else {
-            0
+    fn format_json(&self, line_num: usize, line_str: &str, mode: DecodeMode) -> String {
+        let matches: Vec<String> = self
+            .matches
+            .iter()
+            .enumerate()
+            .map(|(match_idx, digit_match)| {
+                format!(
+                    "{{\"start\":{},\"end\":{},\"text\":{},\"digit\":{},\"first\":{},\"last\":{}}}",
+                    digit_match.start,
+                    digit_match.end,
+                    json_string(&line_str[digit_match.start..digit_match.end]),
+                    digit_match.digit,
+                    self.first_match == Some(match_idx),
+                    self.last_match == Some(match_idx),
+                )
+            })
+            .collect();
+        let (value, error) = match &self.value {
+            Ok(value) => (value.to_string(), "null".to_string()),
+            Err(e) => ("null".to_string(), json_string(e)),
         };
-        let slice_end = i + 1;
+        format!(
+            "{{\"mode\":\"{}\",\"line\":{},\"text\":{},\"matches\":[{}],\"value\":{},\"error\":{}}}",
+            mode.arg_name(),
+            line_num,
+            json_string(line_str),
+            matches.join(","),
+            value,
+            error,
+        )
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[derive(Debug, PartialEq)]
+enum TraceFormat {
+    Table,
+    JsonLines,
+}
+impl FromStr for TraceFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(TraceFormat::Table),
+            "json" => Ok(TraceFormat::JsonLines),
+            _ => Err(format!(
+                "Unknown trace format \"{}\", expected one of \"table\" or \"json\"",
+                s
+            )),
+        }
+    }
+}
+
+/// Exit code used when `--keep-going` skipped at least one line
+const EXIT_CODE_BAD_LINES: i32 = 2;
 
-        for (digit_minus_one, digit_word) in DIGIT_WORDS.iter().enumerate() {
-            if line_str[slice_start..slice_end].ends_with(digit_word) {
-                digits_read = digits_read.push_digit(digit_minus_one as u32 + 1);
-                break;
+/// A line that was skipped by `--keep-going`, and why
+#[derive(Debug, PartialEq)]
+struct BadLine {
+    line_num: usize,
+    reason: String,
+}
+
+/// The sum over every line that could be decoded, plus the lines that couldn't
+#[derive(Debug, PartialEq)]
+struct BatchResult {
+    sum: u32,
+    bad_lines: Vec<BadLine>,
+}
+impl BatchResult {
+    fn from_lines(lines: &[Result<String, String>], matcher: &DigitMatcher) -> Self {
+        let mut sum = 0;
+        let mut bad_lines = vec![];
+        for (line_num, line) in lines.iter().enumerate() {
+            match line
+                .as_ref()
+                .map_err(|e| e.clone())
+                .and_then(|line| get_two_digit_number_from_line(line, matcher))
+            {
+                Ok(two_digit_num) => sum += two_digit_num,
+                Err(reason) => bad_lines.push(BadLine { line_num, reason }),
             }
         }
+        Self { sum, bad_lines }
     }
-    digits_read.try_calculate_two_digit_number()
+}
+
+struct Args {
+    modes: Vec<DecodeMode>,
+    vocabulary_path: Option<String>,
+    trace_format: Option<TraceFormat>,
+    keep_going: bool,
+}
+
+/// Usage: `day-01 [part1|part2] [--vocab <FILE>] [--trace <table|json>] [--keep-going]`
+/// Without a mode both answers are printed. With `--trace` every line is
+/// explained instead of printing the sums. With `--keep-going` lines that can't
+/// be read or decoded are skipped and reported at the end instead of panicking.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut modes = vec![];
+    let mut vocabulary_path = None;
+    let mut trace_format = None;
+    let mut keep_going = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--vocab" => {
+                vocabulary_path = Some(
+                    args.next()
+                        .ok_or("Expected a file path after --vocab".to_string())?,
+                );
+            }
+            "--trace" => {
+                trace_format = Some(
+                    args.next()
+                        .ok_or("Expected a format after --trace".to_string())?
+                        .parse::<TraceFormat>()?,
+                );
+            }
+            "--keep-going" => keep_going = true,
+            mode => modes.push(mode.parse::<DecodeMode>()?),
+        }
+    }
+
+    if modes.is_empty() {
+        modes = vec![DecodeMode::DigitsOnly, DecodeMode::DigitsAndWords];
+    }
+
+    Ok(Args {
+        modes,
+        vocabulary_path,
+        trace_format,
+        keep_going,
+    })
 }
 
 fn main() {
-    let lines = io::stdin().lines();
+    let args = parse_args(std::env::args().skip(1)).unwrap_or_else(|e| panic!("{}", e));
+
+    let vocabulary = match &args.vocabulary_path {
+        Some(path) => std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("Failed to read vocabulary file {}: {}", path, e))
+            .parse::<DigitVocabulary>()
+            .unwrap_or_else(|e| panic!("Failed to parse vocabulary file {}: {}", path, e)),
+        None => DigitVocabulary::english(),
+    };
+
+    let lines: Vec<Result<String, String>> = io::stdin()
+        .lines()
+        .enumerate()
+        .map(|(line_num, line_res)| {
+            line_res.map_err(|e| {
+                if !args.keep_going {
+                    panic!("LINE {}: Failed to read line!", line_num);
+                }
+                format!("Failed to read line: {}", e)
+            })
+        })
+        .collect();
 
-    let mut sum = 0;
+    let mut bad_line_reports = vec![];
+    for mode in args.modes {
+        let matcher = DigitMatcher::new(&vocabulary, mode);
 
-    for (line_num, line_res) in lines.enumerate() {
-        let line = line_res.unwrap_or_else(|_| panic!("LINE {}: Failed to read line!", line_num));
-        let two_digit_num = get_two_digit_number_from_line(&line)
-            .unwrap_or_else(|_| panic!("LINE {}: Failed to parse line!", line_num));
-        sum += two_digit_num;
+        if let Some(trace_format) = &args.trace_format {
+            if *trace_format == TraceFormat::Table {
+                println!("{}", mode.label());
+            }
+            let mut read_errors = vec![];
+            for (line_num, line) in lines.iter().enumerate() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(reason) => {
+                        read_errors.push(BadLine {
+                            line_num,
+                            reason: reason.clone(),
+                        });
+                        continue;
+                    }
+                };
+                let trace = LineTrace::new(line, &matcher);
+                match trace_format {
+                    TraceFormat::Table => println!("{}", trace.format_table(line_num, line)),
+                    TraceFormat::JsonLines => {
+                        println!("{}", trace.format_json(line_num, line, mode))
+                    }
+                }
+            }
+            if !read_errors.is_empty() {
+                bad_line_reports.push((mode, read_errors));
+            }
+            continue;
+        }
+
+        if args.keep_going {
+            let batch = BatchResult::from_lines(&lines, &matcher);
+            println!("{} sum: {}", mode.label(), batch.sum);
+            if !batch.bad_lines.is_empty() {
+                bad_line_reports.push((mode, batch.bad_lines));
+            }
+            continue;
+        }
+
+        let mut sum = 0;
+        for (line_num, line) in lines.iter().enumerate() {
+            let line = line.as_ref().unwrap();
+            let two_digit_num = get_two_digit_number_from_line(line, &matcher)
+                .unwrap_or_else(|_| panic!("LINE {}: Failed to parse line!", line_num));
+            sum += two_digit_num;
+        }
+
+        println!("{} sum: {}", mode.label(), sum);
     }
 
-    println!("Sum: {}", sum);
+    for (mode, bad_lines) in &bad_line_reports {
+        eprintln!("{}: skipped {} bad line(s)", mode.label(), bad_lines.len());
+        for bad_line in bad_lines {
+            eprintln!("  LINE {}: {}", bad_line.line_num, bad_line.reason);
+        }
+    }
+    if !bad_line_reports.is_empty() {
+        std::process::exit(EXIT_CODE_BAD_LINES);
+    }
 }
 
 #[cfg(test)]
 mod test {
-    const TEST_CASES: [(&str, u32); 12] = [
+    use crate::{
+        BadLine, BatchResult, DecodeMode, DigitMatch, DigitMatcher, DigitVocabulary, DigitsRead,
+        LineTrace, BASE_10,
+    };
+
+    fn decode(line: &str, mode: DecodeMode, vocabulary: &DigitVocabulary) -> Result<u32, String> {
+        super::get_two_digit_number_from_line(line, &DigitMatcher::new(vocabulary, mode))
+    }
+
+    /// (line, expected in Part 1 mode, expected in Part 2 mode)
+    /// Part 1 expects `None` when the line has no ASCII digits at all.
+    const TEST_CASES: [(&str, Option<u32>, u32); 12] = [
         // PART 1
-        ("1abc2", 12),
-        ("pqr3stu8vwx", 38),
-        ("a1b2c3d4e5f", 15),
-        ("treb7uchet", 77),
-        ("two1nine", 29),
+        ("1abc2", Some(12), 12),
+        ("pqr3stu8vwx", Some(38), 38),
+        ("a1b2c3d4e5f", Some(15), 15),
+        ("treb7uchet", Some(77), 77),
+        ("two1nine", Some(11), 29),
         // PART 2
-        ("eightwothree", 83),
-        ("abcone2threexyz", 13),
-        ("xtwone3four", 24),
-        ("4nineeightseven2", 42),
-        ("zoneight234", 14),
-        ("7pqrstsixteen", 76),
+        ("eightwothree", None, 83),
+        ("abcone2threexyz", Some(22), 13),
+        ("xtwone3four", Some(33), 24),
+        ("4nineeightseven2", Some(42), 42),
+        ("zoneight234", Some(24), 14),
+        ("7pqrstsixteen", Some(77), 76),
         // EDGE CASES
-        ("twone", 21),
+        ("twone", None, 21),
+    ];
+
+    #[test]
+    fn test_get_two_digit_number_from_line_digits_only() {
+        let english = DigitVocabulary::english();
+        for (line, expected, _) in TEST_CASES.iter() {
+            let actual = decode(line, DecodeMode::DigitsOnly, &english).ok();
+            assert_eq!(*expected, actual, "Failed decoding LINE '{}'!", line);
+        }
+    }
+
+    #[test]
+    fn test_get_two_digit_number_from_line_digits_and_words() {
+        let english = DigitVocabulary::english();
+        for (line, _, expected) in TEST_CASES.iter() {
+            let actual = decode(line, DecodeMode::DigitsAndWords, &english).unwrap();
+            assert_eq!(*expected, actual, "Failed decoding LINE '{}'!", line);
+        }
+    }
+
+    /// Lines with multi-byte characters, which must not panic or shift word matches.
+    /// (line, expected in Part 1 mode, expected in Part 2 mode)
+    const UNICODE_TEST_CASES: [(&str, Option<u32>, Option<u32>); 8] = [
+        ("café7one", Some(77), Some(71)),
+        ("éééééone", None, Some(11)),
+        ("ñtwoñ3ñ", Some(33), Some(23)),
+        ("🎄two🎅nine🎁", None, Some(29)),
+        ("😀😀😀😀😀😀8", Some(88), Some(88)),
+        ("tw😀o1", Some(11), Some(11)),
+        ("fivé6", Some(66), Some(66)),
+        ("日本語", None, None),
     ];
 
     #[test]
-    fn test_get_two_digit_number_from_line() {
-        for (line, expected) in TEST_CASES.iter() {
-            let actual = super::get_two_digit_number_from_line(line).unwrap();
-            assert_eq!(*expected, actual);
+    fn test_get_two_digit_number_from_unicode_lines() {
+        let english = DigitVocabulary::english();
+        for (line, expected_digits_only, expected_digits_and_words) in UNICODE_TEST_CASES.iter() {
+            let actual = decode(line, DecodeMode::DigitsOnly, &english).ok();
+            assert_eq!(
+                *expected_digits_only, actual,
+                "Failed decoding LINE '{}' with digits only!",
+                line
+            );
+
+            let actual = decode(line, DecodeMode::DigitsAndWords, &english).ok();
+            assert_eq!(
+                *expected_digits_and_words, actual,
+                "Failed decoding LINE '{}' with digits and words!",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_decodes_with_custom_vocabulary() {
+        let vocabulary = "\
+# German, plus a word for zero
+null 0
+eins 1
+zwei 2
+drei 3
+vier 4
+fünf 5
+"
+        .parse::<DigitVocabulary>()
+        .unwrap();
+        assert_eq!(vocabulary.words.len(), 6);
+
+        let test_cases = [
+            ("nullxyzeins", Some(1)),
+            ("a9zweinull", Some(90)),
+            ("fünfvierdrei", Some(53)),
+            ("onetwothree", None),
+        ];
+        for (line, expected) in test_cases {
+            let actual = decode(line, DecodeMode::DigitsAndWords, &vocabulary).ok();
+            assert_eq!(expected, actual, "Failed decoding LINE '{}'!", line);
+        }
+    }
+
+    #[test]
+    fn test_custom_vocabulary_keeps_overlapping_words() {
+        let vocabulary =
+            DigitVocabulary::new(vec![("two".to_string(), 2), ("one".to_string(), 1)]).unwrap();
+        assert_eq!(
+            decode("twone", DecodeMode::DigitsAndWords, &vocabulary),
+            Ok(21)
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_vocabularies() {
+        assert!("ten 10".parse::<DigitVocabulary>().is_err());
+        assert!("one 1\none 2".parse::<DigitVocabulary>().is_err());
+        assert!("one".parse::<DigitVocabulary>().is_err());
+        assert!("one x".parse::<DigitVocabulary>().is_err());
+        assert!(DigitVocabulary::new(vec![("".to_string(), 1)]).is_err());
+    }
+
+    /// The original scanner, which checks every vocabulary word against a sliding
+    /// window ending at each character. Kept as the reference for the automaton.
+    fn reference_two_digit_number_from_line(
+        line_str: &str,
+        mode: DecodeMode,
+        vocabulary: &DigitVocabulary,
+    ) -> Result<u32, String> {
+        let max_word_len = vocabulary
+            .words
+            .iter()
+            .map(|(word, _)| word.len())
+            .max()
+            .unwrap_or(0);
+
+        let mut digits_read = DigitsRead::NoDigits;
+        for (byte_offset, c) in line_str.char_indices() {
+            if c.is_digit(BASE_10) {
+                digits_read = digits_read.push_digit(c.to_digit(BASE_10).unwrap());
+                continue;
+            }
+
+            if mode == DecodeMode::DigitsOnly {
+                continue;
+            }
+
+            let slice_end = byte_offset + c.len_utf8();
+            let mut slice_start = slice_end.saturating_sub(max_word_len);
+            while !line_str.is_char_boundary(slice_start) {
+                slice_start += 1;
+            }
+
+            if let Some((_, digit)) = vocabulary
+                .words
+                .iter()
+                .find(|(word, _)| line_str[slice_start..slice_end].ends_with(word.as_str()))
+            {
+                digits_read = digits_read.push_digit(*digit);
+            }
         }
+        digits_read.try_calculate_two_digit_number()
+    }
+
+    /// Deterministic pseudo random lines built from digit word fragments, digits,
+    /// filler and multi-byte characters, so words overlap and get cut off often.
+    fn synthetic_lines(seed: u64, total_bytes: usize) -> Vec<String> {
+        const FRAGMENTS: [&str; 24] = [
+            "one", "tw", "o", "thr", "ee", "four", "fi", "ve", "six", "sev", "en", "eight", "nin",
+            "e", "t", "x", "q", "1", "7", "0", "é", "🎄", "zwei", "null",
+        ];
+
+        let mut state = seed;
+        let mut next_random = move |bound: usize| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((state >> 33) as usize) % bound
+        };
+
+        let mut lines = vec![];
+        let mut bytes_generated = 0;
+        while bytes_generated < total_bytes {
+            let num_fragments = next_random(64);
+            let line: String = (0..num_fragments)
+                .map(|_| FRAGMENTS[next_random(FRAGMENTS.len())])
+                .collect();
+            bytes_generated += line.len() + 1;
+            lines.push(line);
+        }
+        lines
+    }
+
+    #[test]
+    fn test_matcher_agrees_with_reference_scanner_on_synthetic_input() {
+        let vocabularies = [
+            DigitVocabulary::english(),
+            "null 0\neins 1\nzwei 2\nnine 9\nne 4\nninet 6"
+                .parse::<DigitVocabulary>()
+                .unwrap(),
+        ];
+        let lines = synthetic_lines(2023, 2 * 1024 * 1024);
+
+        for vocabulary in &vocabularies {
+            for mode in [DecodeMode::DigitsOnly, DecodeMode::DigitsAndWords] {
+                let matcher = DigitMatcher::new(vocabulary, mode);
+                for line in &lines {
+                    assert_eq!(
+                        super::get_two_digit_number_from_line(line, &matcher),
+                        reference_two_digit_number_from_line(line, mode, vocabulary),
+                        "Matcher disagreed with reference scanner on LINE '{}' in {:?}",
+                        line,
+                        mode,
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_last_digit_is_match_that_ends_last() {
+        // "bc" is found first when scanning from the back, but "abcd" ends later
+        let vocabulary = "abcd 1\nbc 2".parse::<DigitVocabulary>().unwrap();
+        assert_eq!(
+            decode("xabcd", DecodeMode::DigitsAndWords, &vocabulary),
+            Ok(21)
+        );
+    }
+
+    #[test]
+    fn test_traces_every_match_and_marks_first_and_last() {
+        let matcher = DigitMatcher::new(&DigitVocabulary::english(), DecodeMode::DigitsAndWords);
+        let trace = LineTrace::new("xtwone3four", &matcher);
+
+        assert_eq!(
+            trace.matches,
+            vec![
+                DigitMatch {
+                    start: 1,
+                    end: 4,
+                    digit: 2
+                },
+                DigitMatch {
+                    start: 3,
+                    end: 6,
+                    digit: 1
+                },
+                DigitMatch {
+                    start: 6,
+                    end: 7,
+                    digit: 3
+                },
+                DigitMatch {
+                    start: 7,
+                    end: 11,
+                    digit: 4
+                },
+            ]
+        );
+        assert_eq!(trace.first_match, Some(0));
+        assert_eq!(trace.last_match, Some(3));
+        assert_eq!(trace.value, Ok(24));
+    }
+
+    #[test]
+    fn test_trace_value_agrees_with_matcher() {
+        let english = DigitVocabulary::english();
+        for mode in [DecodeMode::DigitsOnly, DecodeMode::DigitsAndWords] {
+            let matcher = DigitMatcher::new(&english, mode);
+            for line in synthetic_lines(25, 64 * 1024) {
+                assert_eq!(
+                    LineTrace::new(&line, &matcher).value,
+                    super::get_two_digit_number_from_line(&line, &matcher),
+                    "Trace disagreed with matcher on LINE '{}' in {:?}",
+                    line,
+                    mode,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_formats_trace() {
+        let matcher = DigitMatcher::new(&DigitVocabulary::english(), DecodeMode::DigitsAndWords);
+
+        let trace = LineTrace::new("a\"one\"", &matcher);
+        assert_eq!(
+            trace.format_json(3, "a\"one\"", DecodeMode::DigitsAndWords),
+            r#"{"mode":"part2","line":3,"text":"a\"one\"","matches":[{"start":2,"end":5,"text":"one","digit":1,"first":true,"last":true}],"value":11,"error":null}"#
+        );
+        assert!(trace
+            .format_table(3, "a\"one\"")
+            .contains("2..5       one          1     first+last"));
+
+        let trace = LineTrace::new("abc", &matcher);
+        assert_eq!(
+            trace.format_json(0, "abc", DecodeMode::DigitsAndWords),
+            r#"{"mode":"part2","line":0,"text":"abc","matches":[],"value":null,"error":"No digits read!"}"#
+        );
+        assert!(trace
+            .format_table(0, "abc")
+            .ends_with("ERROR: No digits read!\n"));
+    }
+
+    #[test]
+    fn test_batch_sums_valid_lines_and_collects_bad_ones() {
+        let lines = vec![
+            Ok("1abc2".to_string()),
+            Ok("eightwothree".to_string()),
+            Err("Failed to read line: stream did not contain valid UTF-8".to_string()),
+            Ok("treb7uchet".to_string()),
+            Ok("".to_string()),
+        ];
+        let matcher = DigitMatcher::new(&DigitVocabulary::english(), DecodeMode::DigitsOnly);
+
+        assert_eq!(
+            BatchResult::from_lines(&lines, &matcher),
+            BatchResult {
+                sum: 12 + 77,
+                bad_lines: vec![
+                    BadLine {
+                        line_num: 1,
+                        reason: "No digits read!".to_string()
+                    },
+                    BadLine {
+                        line_num: 2,
+                        reason: "Failed to read line: stream did not contain valid UTF-8"
+                            .to_string()
+                    },
+                    BadLine {
+                        line_num: 4,
+                        reason: "No digits read!".to_string()
+                    },
+                ]
+            }
+        );
+    }
+
+    #[test]
+    fn test_parses_decode_mode() {
+        assert_eq!("part1".parse(), Ok(DecodeMode::DigitsOnly));
+        assert_eq!("part2".parse(), Ok(DecodeMode::DigitsAndWords));
+        assert!("part3".parse::<DecodeMode>().is_err());
     }
 }
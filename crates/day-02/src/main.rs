@@ -1,22 +1,90 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Range,
+    str::FromStr,
+};
 
+use stats::{Stats, StatsFormat};
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+struct CubeColor(String);
+impl From<&str> for CubeColor {
+    fn from(value: &str) -> Self {
+        CubeColor(value.to_string())
+    }
+}
+impl std::fmt::Display for CubeColor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Number of cubes of each colour in a bag. Colours missing from the bag have no cubes.
 #[derive(Debug, PartialEq)]
 struct CubeBag {
-    red_cubes: u32,
-    green_cubes: u32,
-    blue_cubes: u32,
+    cubes: BTreeMap<CubeColor, u32>,
 }
 impl CubeBag {
-    fn power(&self) -> u32 {
-        self.red_cubes * self.green_cubes * self.blue_cubes
+    fn num_cubes(&self, color: &CubeColor) -> u32 {
+        *self.cubes.get(color).unwrap_or(&0)
+    }
+
+    /// Product of the cube counts of every colour in the bag, 0 for an empty bag
+    fn power(&self) -> Result<u64, String> {
+        if self.cubes.is_empty() {
+            return Ok(0);
+        }
+        self.cubes
+            .values()
+            .try_fold(1u64, |power, num_cubes| {
+                power.checked_mul(*num_cubes as u64)
+            })
+            .ok_or_else(|| "Power of bag overflowed u64".to_string())
     }
 }
+impl FromStr for CubeBag {
+    type Err = String;
 
-#[derive(Debug, Hash, PartialEq, Eq)]
-enum CubeColor {
-    Red,
-    Green,
-    Blue,
+    /// Parses a bag written like a turn, e.g. "12 red, 13 green, 14 blue"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut cubes = BTreeMap::new();
+        for cubes_in_bag in s.split(',').map(str::trim) {
+            let (num_cubes, color) = cubes_in_bag
+                .split_once(' ')
+                .ok_or("Expected a space separating number of cubes and color in bag")?;
+            let num_cubes = num_cubes
+                .parse::<u32>()
+                .map_err(|_| format!("Failed to parse number of {} cubes in bag", color))?;
+            if color.is_empty() {
+                return Err("Expected a color after number of cubes in bag".to_string());
+            }
+            check_color_name(color)?;
+            if cubes.insert(CubeColor::from(color), num_cubes).is_some() {
+                return Err(format!(
+                    "Number of {} cubes in bag set more than once",
+                    color
+                ));
+            }
+        }
+        Ok(CubeBag { cubes })
+    }
+}
+impl std::fmt::Display for CubeBag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cubes: Vec<String> = self
+            .cubes
+            .iter()
+            .map(|(color, num_cubes)| format!("{} {}", num_cubes, color))
+            .collect();
+        write!(f, "{}", cubes.join(", "))
+    }
+}
+impl FromIterator<(CubeColor, u32)> for CubeBag {
+    fn from_iter<T: IntoIterator<Item = (CubeColor, u32)>>(iter: T) -> Self {
+        CubeBag {
+            cubes: iter.into_iter().collect(),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -30,82 +98,943 @@ impl GameTurn {
         }
     }
 
-    fn with_red(mut self, red_cubes: u32) -> Result<Self, String> {
-        match self.cubes_drawn.get(&CubeColor::Red) {
+    fn with_cubes(mut self, color: CubeColor, num_cubes: u32) -> Result<Self, String> {
+        match self.cubes_drawn.get(&color) {
             None => {
-                self.cubes_drawn.insert(CubeColor::Red, red_cubes);
+                self.cubes_drawn.insert(color, num_cubes);
                 Ok(self)
             }
-            Some(_) => Err("Already set number of red cubes drawn this turn".to_string()),
+            Some(_) => Err(format!(
+                "Already set number of {} cubes drawn this turn",
+                color
+            )),
         }
     }
+}
 
-    fn with_green(mut self, green_cubes: u32) -> Result<Self, String> {
-        match self.cubes_drawn.get(&CubeColor::Green) {
-            None => {
-                self.cubes_drawn.insert(CubeColor::Green, green_cubes);
-                Ok(self)
+/// A colour in a turn that drew more cubes than the bag holds
+#[derive(Debug, PartialEq)]
+struct Violation {
+    /// Index into `Game::turns`, starting at 0
+    turn_index: usize,
+    color: CubeColor,
+    num_drawn: u32,
+    bag_limit: u32,
+}
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "TURN {}: drew {} {} but the bag only has {}",
+            self.turn_index + 1,
+            self.num_drawn,
+            self.color,
+            self.bag_limit
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Game {
+    number: usize,
+    turns: Vec<GameTurn>,
+}
+impl Game {
+    fn is_possible_with_bag(&self, bag: &CubeBag) -> bool {
+        self.violations_with_bag(bag).is_empty()
+    }
+
+    /// Every draw that makes this game impossible with the bag, ordered by turn then colour
+    fn violations_with_bag(&self, bag: &CubeBag) -> Vec<Violation> {
+        let mut violations = vec![];
+        for (turn_index, turn) in self.turns.iter().enumerate() {
+            let mut turn_violations: Vec<Violation> = turn
+                .cubes_drawn
+                .iter()
+                .filter(|(color, num_drawn)| bag.num_cubes(color) < **num_drawn)
+                .map(|(color, num_drawn)| Violation {
+                    turn_index,
+                    color: color.clone(),
+                    num_drawn: *num_drawn,
+                    bag_limit: bag.num_cubes(color),
+                })
+                .collect();
+            turn_violations.sort_by(|a, b| a.color.cmp(&b.color));
+            violations.extend(turn_violations);
+        }
+        violations
+    }
+
+    /// The smallest bag that makes this game possible, with an entry for every colour
+    /// in `colors` and every colour drawn. Colours the game never drew have 0 cubes.
+    fn min_cube_bag(&self, colors: &[CubeColor]) -> CubeBag {
+        let mut max_drawn: BTreeMap<CubeColor, u32> =
+            colors.iter().map(|color| (color.clone(), 0)).collect();
+        for turn in &self.turns {
+            for (color, num_drawn) in &turn.cubes_drawn {
+                let max_drawn_for_color = max_drawn.entry(color.clone()).or_insert(0);
+                *max_drawn_for_color = (*max_drawn_for_color).max(*num_drawn);
             }
-            Some(_) => Err("Already set number of green cubes drawn this turn".to_string()),
+        }
+        CubeBag { cubes: max_drawn }
+    }
+}
+
+/// Every colour drawn in any game, in order
+fn colors_drawn(games: &[Game]) -> Vec<CubeColor> {
+    let mut colors: Vec<CubeColor> = games
+        .iter()
+        .flat_map(|game| game.turns.iter())
+        .flat_map(|turn| turn.cubes_drawn.keys().cloned())
+        .collect();
+    colors.sort();
+    colors.dedup();
+    colors
+}
+
+/// Sum of the powers of every game's min cube bag, over all colours in the games
+fn sum_of_min_cube_bag_powers(games: &[Game]) -> Result<u64, String> {
+    let colors = colors_drawn(games);
+    games.iter().try_fold(0u64, |sum, game| {
+        let power = game
+            .min_cube_bag(&colors)
+            .power()
+            .map_err(|msg| format!("GAME {}: {}", game.number, msg))?;
+        sum.checked_add(power)
+            .ok_or_else(|| "Sum of powers of min cube bags overflowed u64".to_string())
+    })
+}
+
+/// Colours are letters and spaces, like "dark red", with no space at either end
+fn check_color_name(color: &str) -> Result<(), String> {
+    if !color.chars().all(|c| c.is_alphabetic() || c == ' ') {
+        return Err(format!(
+            "Expected color to only contain letters and spaces, got \"{}\"",
+            color
+        ));
+    }
+    if color.trim() != color {
+        return Err(format!(
+            "Color \"{}\" has leading or trailing spaces",
+            color
+        ));
+    }
+    Ok(())
+}
+
+/// Statistical estimates of what was in a bag, given the turns drawn from it.
+///
+/// Each turn is modelled as drawing a handful of cubes from the bag without
+/// replacement, with the cubes put back before the next turn. So the chance of
+/// a turn is multivariate hypergeometric: the product over colours of
+/// C(cubes of colour, cubes drawn of colour) divided by C(cubes in bag, cubes drawn).
+mod estimate {
+    use super::{CubeBag, CubeColor, GameTurn};
+
+    /// Half the 95% quantile of chi-squared with one degree of freedom. Counts
+    /// whose profile log-likelihood is within this of the maximum are in the range.
+    const CONFIDENCE_95_LOG_LIKELIHOOD_DROP: f64 = 1.920729;
+
+    /// Counts are searched up to this many times the most cubes seen in one turn
+    /// (plus `SEARCH_LIMIT_EXTRA`), since a colour that dominates every handful
+    /// can be explained equally well by any larger count.
+    const SEARCH_LIMIT_FACTOR: u32 = 4;
+    const SEARCH_LIMIT_EXTRA: u32 = 10;
+
+    /// Bags with more cubes than this, counting every colour, are neither searched
+    /// nor scored, as the search and the table of log factorials grow with the count.
+    /// The search for each colour also stops here.
+    const MAX_CUBES_IN_BAG: u32 = 1000;
+
+    /// Improvements smaller than this are treated as ties, which go to the smaller bag
+    const LOG_LIKELIHOOD_EPSILON: f64 = 1e-9;
+
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct ColorEstimate {
+        pub(crate) color: CubeColor,
+        /// Most likely number of cubes of this colour
+        pub(crate) num_cubes: u32,
+        /// Smallest count in the 95% confidence range, never below the most ever drawn
+        pub(crate) range_start: u32,
+        /// Largest count in the 95% confidence range
+        pub(crate) range_end: u32,
+        /// The range reached the search limit, so there is no real upper bound
+        pub(crate) range_is_open: bool,
+    }
+    impl std::fmt::Display for ColorEstimate {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(
+                f,
+                "{} {} (95% range {}..={}{})",
+                self.num_cubes,
+                self.color,
+                self.range_start,
+                self.range_end,
+                if self.range_is_open { "+" } else { "" }
+            )
         }
     }
 
-    fn with_blue(mut self, blue_cubes: u32) -> Result<Self, String> {
-        match self.cubes_drawn.get(&CubeColor::Blue) {
-            None => {
-                self.cubes_drawn.insert(CubeColor::Blue, blue_cubes);
-                Ok(self)
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct BagEstimate {
+        pub(crate) colors: Vec<ColorEstimate>,
+        pub(crate) log_likelihood: f64,
+    }
+
+    fn too_many_cubes() -> String {
+        format!(
+            "Too many cubes to estimate, bags of at most {} cubes are supported",
+            MAX_CUBES_IN_BAG
+        )
+    }
+
+    /// Sum of the counts, or an error when it is over `MAX_CUBES_IN_BAG`
+    fn total_cubes(counts: &[u32]) -> Result<u32, String> {
+        counts
+            .iter()
+            .try_fold(0u32, |total, count| total.checked_add(*count))
+            .filter(|total| *total <= MAX_CUBES_IN_BAG)
+            .ok_or_else(too_many_cubes)
+    }
+
+    /// Natural log of n! for every n up to the size of the largest bag searched
+    struct LnFactorials(Vec<f64>);
+    impl LnFactorials {
+        fn up_to(max_n: u32) -> Self {
+            let mut ln_factorials = vec![0.0; max_n as usize + 1];
+            for n in 1..=max_n as usize {
+                ln_factorials[n] = ln_factorials[n - 1] + (n as f64).ln();
             }
-            Some(_) => Err("Already set number of blue cubes drawn this turn".to_string()),
+            Self(ln_factorials)
+        }
+
+        fn ln_choose(&self, n: u32, k: u32) -> f64 {
+            if k > n {
+                return f64::NEG_INFINITY;
+            }
+            self.0[n as usize] - self.0[k as usize] - self.0[(n - k) as usize]
         }
     }
 
-    fn num_red_drawn(&self) -> u32 {
-        *self.cubes_drawn.get(&CubeColor::Red).unwrap_or(&0)
+    /// Turns flattened into counts per colour, indexed the same as `colors`
+    struct Observations {
+        colors: Vec<CubeColor>,
+        turns: Vec<Vec<u32>>,
     }
+    impl Observations {
+        fn new<'a>(turns: impl Iterator<Item = &'a GameTurn>) -> Self {
+            let turns: Vec<&GameTurn> = turns.collect();
+            let mut colors: Vec<CubeColor> = turns
+                .iter()
+                .flat_map(|turn| turn.cubes_drawn.keys().cloned())
+                .collect();
+            colors.sort();
+            colors.dedup();
+
+            let turns = turns
+                .iter()
+                .map(|turn| {
+                    colors
+                        .iter()
+                        .map(|color| *turn.cubes_drawn.get(color).unwrap_or(&0))
+                        .collect()
+                })
+                .collect();
+            Self { colors, turns }
+        }
+
+        fn max_drawn(&self, color_idx: usize) -> u32 {
+            self.turns
+                .iter()
+                .map(|turn| turn[color_idx])
+                .max()
+                .unwrap_or(0)
+        }
 
-    fn num_green_drawn(&self) -> u32 {
-        *self.cubes_drawn.get(&CubeColor::Green).unwrap_or(&0)
+        fn log_likelihood(&self, ln_factorials: &LnFactorials, counts: &[u32]) -> f64 {
+            let total_cubes: u32 = counts.iter().sum();
+            self.turns
+                .iter()
+                .map(|turn| {
+                    let num_drawn: u32 = turn.iter().sum();
+                    turn.iter()
+                        .zip(counts)
+                        .map(|(drawn, count)| ln_factorials.ln_choose(*count, *drawn))
+                        .sum::<f64>()
+                        - ln_factorials.ln_choose(total_cubes, num_drawn)
+                })
+                .sum()
+        }
     }
 
-    fn num_blue_drawn(&self) -> u32 {
-        *self.cubes_drawn.get(&CubeColor::Blue).unwrap_or(&0)
+    struct Search<'a> {
+        observations: &'a Observations,
+        ln_factorials: LnFactorials,
+        lower_limits: Vec<u32>,
+        upper_limits: Vec<u32>,
     }
-}
+    impl<'a> Search<'a> {
+        fn new(observations: &'a Observations) -> Result<Self, String> {
+            let lower_limits: Vec<u32> = (0..observations.colors.len())
+                .map(|color_idx| observations.max_drawn(color_idx))
+                .collect();
+            total_cubes(&lower_limits)?;
+            let upper_limits: Vec<u32> = lower_limits
+                .iter()
+                .map(|max_drawn| {
+                    max_drawn
+                        .checked_mul(SEARCH_LIMIT_FACTOR)
+                        .and_then(|limit| limit.checked_add(SEARCH_LIMIT_EXTRA))
+                        .map(|limit| limit.min(MAX_CUBES_IN_BAG))
+                        .ok_or_else(too_many_cubes)
+                })
+                .collect::<Result<_, _>>()?;
+            let ln_factorials = LnFactorials::up_to(upper_limits.iter().sum());
+            Ok(Self {
+                observations,
+                ln_factorials,
+                lower_limits,
+                upper_limits,
+            })
+        }
 
-#[derive(Debug, PartialEq)]
-struct Game {
-    number: usize,
-    turns: Vec<GameTurn>,
+        fn log_likelihood(&self, counts: &[u32]) -> f64 {
+            self.observations
+                .log_likelihood(&self.ln_factorials, counts)
+        }
+
+        /// Coordinate ascent from `counts`, never changing the colour at `fixed_idx`.
+        /// Each colour in turn moves to its best count given the others, until no
+        /// colour moves. Counts only move on a strict improvement, and are tried
+        /// smallest first, so ties are broken towards smaller bags.
+        fn maximize(&self, mut counts: Vec<u32>, fixed_idx: Option<usize>) -> (Vec<u32>, f64) {
+            let mut best_log_likelihood = self.log_likelihood(&counts);
+            loop {
+                let mut improved = false;
+                for color_idx in 0..counts.len() {
+                    if Some(color_idx) == fixed_idx {
+                        continue;
+                    }
+                    let mut candidate = counts.clone();
+                    for count in self.lower_limits[color_idx]..=self.upper_limits[color_idx] {
+                        candidate[color_idx] = count;
+                        let log_likelihood = self.log_likelihood(&candidate);
+                        if log_likelihood > best_log_likelihood + LOG_LIKELIHOOD_EPSILON {
+                            best_log_likelihood = log_likelihood;
+                            counts[color_idx] = count;
+                            improved = true;
+                        }
+                    }
+                }
+                if !improved {
+                    return (counts, best_log_likelihood);
+                }
+            }
+        }
+
+        /// Every count of the colour whose profile log-likelihood (the others
+        /// re-maximized around it) is at least `threshold`
+        fn confidence_range(
+            &self,
+            best_counts: &[u32],
+            color_idx: usize,
+            threshold: f64,
+        ) -> (u32, u32) {
+            let mut range: Option<(u32, u32)> = None;
+            for count in self.lower_limits[color_idx]..=self.upper_limits[color_idx] {
+                let mut counts = best_counts.to_vec();
+                counts[color_idx] = count;
+                let (_, log_likelihood) = self.maximize(counts, Some(color_idx));
+                if log_likelihood >= threshold {
+                    range = Some(match range {
+                        Some((start, _)) => (start, count),
+                        None => (count, count),
+                    });
+                }
+            }
+            range.unwrap()
+        }
+    }
+
+    /// The maximum likelihood bag for the turns, with a 95% confidence range per colour.
+    /// Only colours that were drawn at least once can be estimated.
+    pub(crate) fn estimate_bag<'a>(
+        turns: impl Iterator<Item = &'a GameTurn>,
+    ) -> Result<BagEstimate, String> {
+        let observations = Observations::new(turns);
+        let search = Search::new(&observations)?;
+
+        let (best_counts, log_likelihood) = search.maximize(search.lower_limits.clone(), None);
+        let threshold = log_likelihood - CONFIDENCE_95_LOG_LIKELIHOOD_DROP;
+
+        let colors = observations
+            .colors
+            .iter()
+            .enumerate()
+            .map(|(color_idx, color)| {
+                let (range_start, range_end) =
+                    search.confidence_range(&best_counts, color_idx, threshold);
+                ColorEstimate {
+                    color: color.clone(),
+                    num_cubes: best_counts[color_idx],
+                    range_start,
+                    range_end,
+                    range_is_open: range_end == search.upper_limits[color_idx],
+                }
+            })
+            .collect();
+
+        Ok(BagEstimate {
+            colors,
+            log_likelihood,
+        })
+    }
+
+    /// Log-likelihood of the turns if they were drawn from `bag`.
+    /// Negative infinity if the bag can't produce one of the turns.
+    pub(crate) fn bag_log_likelihood<'a>(
+        turns: impl Iterator<Item = &'a GameTurn>,
+        bag: &CubeBag,
+    ) -> Result<f64, String> {
+        let turns: Vec<&GameTurn> = turns.collect();
+        let colors: Vec<&CubeColor> = bag.cubes.keys().collect();
+        if turns.iter().any(|turn| {
+            turn.cubes_drawn
+                .iter()
+                .any(|(color, num_drawn)| *num_drawn > bag.num_cubes(color))
+        }) {
+            return Ok(f64::NEG_INFINITY);
+        }
+
+        let observations = Observations {
+            turns: turns
+                .iter()
+                .map(|turn| {
+                    colors
+                        .iter()
+                        .map(|color| *turn.cubes_drawn.get(*color).unwrap_or(&0))
+                        .collect()
+                })
+                .collect(),
+            colors: colors.into_iter().cloned().collect(),
+        };
+        let counts: Vec<u32> = bag.cubes.values().copied().collect();
+        let ln_factorials = LnFactorials::up_to(total_cubes(&counts)?);
+        Ok(observations.log_likelihood(&ln_factorials, &counts))
+    }
+
+    /// Indexes of `bags`, from the one that best explains the turns to the worst
+    pub(crate) fn rank_bags<'a>(
+        turns: impl Iterator<Item = &'a GameTurn> + Clone,
+        bags: &[&CubeBag],
+    ) -> Result<Vec<(usize, f64)>, String> {
+        let mut ranked: Vec<(usize, f64)> = bags
+            .iter()
+            .enumerate()
+            .map(|(bag_idx, bag)| Ok((bag_idx, bag_log_likelihood(turns.clone(), bag)?)))
+            .collect::<Result<_, String>>()?;
+        ranked.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        Ok(ranked)
+    }
+
+    #[cfg(test)]
+    mod test {
+        use std::collections::HashMap;
+
+        use super::{bag_log_likelihood, estimate_bag, rank_bags};
+        use crate::{CubeBag, CubeColor, GameTurn};
+
+        fn cube_bag(cubes: &[(&str, u32)]) -> CubeBag {
+            cubes
+                .iter()
+                .map(|(color, num_cubes)| (CubeColor::from(*color), *num_cubes))
+                .collect()
+        }
+
+        /// Draws handfuls without replacement from the bag, putting them back between
+        /// turns, with a fixed seed so the games are the same on every run
+        fn synthetic_turns(bag: &CubeBag, num_turns: usize, seed: u64) -> Vec<GameTurn> {
+            let mut state = seed;
+            let mut next_random = move |bound: usize| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                ((state >> 33) as usize) % bound
+            };
+
+            let mut cubes: Vec<&CubeColor> = bag
+                .cubes
+                .iter()
+                .flat_map(|(color, num_cubes)| std::iter::repeat_n(color, *num_cubes as usize))
+                .collect();
+
+            (0..num_turns)
+                .map(|_| {
+                    let handful_size = 1 + next_random(cubes.len() / 2);
+                    for i in 0..handful_size {
+                        let j = i + next_random(cubes.len() - i);
+                        cubes.swap(i, j);
+                    }
+                    let mut cubes_drawn = HashMap::new();
+                    for color in &cubes[..handful_size] {
+                        *cubes_drawn.entry((*color).clone()).or_insert(0) += 1;
+                    }
+                    GameTurn { cubes_drawn }
+                })
+                .collect()
+        }
+
+        #[test]
+        fn test_estimate_covers_true_bag() {
+            let true_bag = cube_bag(&[("red", 12), ("green", 13), ("blue", 14)]);
+            let turns = synthetic_turns(&true_bag, 40, 2023);
+
+            let estimate = estimate_bag(turns.iter()).unwrap();
+            for color_estimate in &estimate.colors {
+                let true_count = true_bag.num_cubes(&color_estimate.color);
+                assert!(
+                    color_estimate.range_start <= true_count
+                        && true_count <= color_estimate.range_end,
+                    "True count {} outside estimate {}",
+                    true_count,
+                    color_estimate
+                );
+                assert!(color_estimate.range_start <= color_estimate.num_cubes);
+                assert!(color_estimate.num_cubes <= color_estimate.range_end);
+            }
+            assert!(
+                estimate.log_likelihood >= bag_log_likelihood(turns.iter(), &true_bag).unwrap()
+            );
+        }
+
+        #[test]
+        fn test_single_turn_estimates_min_bag() {
+            let turns = [GameTurn {
+                cubes_drawn: HashMap::from([
+                    (CubeColor::from("red"), 3),
+                    (CubeColor::from("blue"), 4),
+                ]),
+            }];
+
+            let estimate = estimate_bag(turns.iter()).unwrap();
+            let num_cubes: Vec<(String, u32)> = estimate
+                .colors
+                .iter()
+                .map(|estimate| (estimate.color.to_string(), estimate.num_cubes))
+                .collect();
+            assert_eq!(
+                num_cubes,
+                vec![("blue".to_string(), 4), ("red".to_string(), 3)]
+            );
+            assert_eq!(estimate.log_likelihood, 0.0);
+        }
+
+        #[test]
+        fn test_ranks_true_bag_first() {
+            let true_bag = cube_bag(&[("red", 12), ("green", 13), ("blue", 14)]);
+            let candidates = [
+                cube_bag(&[("red", 5), ("green", 5), ("blue", 5)]),
+                cube_bag(&[("red", 20), ("green", 13), ("blue", 6)]),
+                true_bag,
+                cube_bag(&[("red", 6), ("green", 13), ("blue", 20)]),
+            ];
+            let turns = synthetic_turns(&candidates[2], 40, 25);
+
+            let ranked = rank_bags(turns.iter(), &candidates.iter().collect::<Vec<_>>()).unwrap();
+            assert_eq!(ranked[0].0, 2);
+            assert!(ranked.contains(&(0, f64::NEG_INFINITY)));
+        }
+
+        #[test]
+        fn test_rejects_bags_too_big_to_estimate() {
+            let turns = [GameTurn {
+                cubes_drawn: HashMap::from([(CubeColor::from("red"), 1_000_000_000)]),
+            }];
+            assert!(estimate_bag(turns.iter())
+                .unwrap_err()
+                .starts_with("Too many cubes to estimate"));
+
+            let turns = [GameTurn {
+                cubes_drawn: HashMap::from([(CubeColor::from("red"), u32::MAX)]),
+            }];
+            assert!(estimate_bag(turns.iter()).is_err());
+
+            let huge_bag = cube_bag(&[("red", u32::MAX), ("blue", 1)]);
+            assert!(bag_log_likelihood(std::iter::empty(), &huge_bag).is_err());
+            assert!(rank_bags(std::iter::empty(), &[&huge_bag]).is_err());
+        }
+    }
 }
-impl Game {
-    fn is_possible_with_bag(&self, bag: &CubeBag) -> bool {
-        let mut max_red_drawn = 0;
-        let mut max_green_drawn = 0;
-        let mut max_blue_drawn = 0;
-        for turn in &self.turns {
-            max_red_drawn = max_red_drawn.max(turn.num_red_drawn());
-            max_green_drawn = max_green_drawn.max(turn.num_green_drawn());
-            max_blue_drawn = max_blue_drawn.max(turn.num_blue_drawn());
+
+/// Summary statistics over a whole game log, as a text table or CSV
+mod stats {
+    use std::{collections::BTreeMap, str::FromStr};
+
+    use super::{colors_drawn, CubeColor, Game, NamedBag};
+
+    const NUM_POWER_HISTOGRAM_BUCKETS: u64 = 10;
+    const NUM_CLOSEST_TO_IMPOSSIBLE: usize = 5;
+    const HISTOGRAM_BAR_WIDTH: usize = 40;
+
+    #[derive(Debug, PartialEq, Clone, Copy, Default)]
+    pub(crate) enum StatsFormat {
+        #[default]
+        Text,
+        Csv,
+    }
+    impl FromStr for StatsFormat {
+        type Err = String;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "text" => Ok(StatsFormat::Text),
+                "csv" => Ok(StatsFormat::Csv),
+                _ => Err(format!(
+                    "Unknown stats format \"{}\", expected one of \"text\" or \"csv\"",
+                    s
+                )),
+            }
         }
-        bag.red_cubes >= max_red_drawn
-            && bag.green_cubes >= max_green_drawn
-            && bag.blue_cubes >= max_blue_drawn
     }
 
-    fn min_cube_bag(&self) -> CubeBag {
-        let mut max_red_drawn = 0;
-        let mut max_green_drawn = 0;
-        let mut max_blue_drawn = 0;
-        for turn in &self.turns {
-            max_red_drawn = max_red_drawn.max(turn.num_red_drawn());
-            max_green_drawn = max_green_drawn.max(turn.num_green_drawn());
-            max_blue_drawn = max_blue_drawn.max(turn.num_blue_drawn());
+    /// Cubes of one colour drawn per turn, counting turns that didn't draw it as 0
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct ColorStats {
+        pub(crate) color: CubeColor,
+        pub(crate) min: u32,
+        pub(crate) max: u32,
+        pub(crate) mean: f64,
+        /// How many turns drew at least one cube of this colour
+        pub(crate) turns_drawn_in: usize,
+    }
+
+    /// Number of games whose min cube bag power is in `start..=end`
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct HistogramBucket {
+        pub(crate) start: u64,
+        pub(crate) end: u64,
+        pub(crate) count: usize,
+    }
+
+    /// How close a possible game came to being impossible with a bag: the fewest
+    /// cubes the bag had to spare for any colour, and which colour that was
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct GameSlack {
+        pub(crate) game_number: usize,
+        pub(crate) slack: u32,
+        pub(crate) tightest_color: CubeColor,
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct Stats {
+        pub(crate) num_games: usize,
+        pub(crate) colors: Vec<ColorStats>,
+        pub(crate) power_histogram: Vec<HistogramBucket>,
+        /// Number of turns -> number of games with that many turns
+        pub(crate) turns_per_game: BTreeMap<usize, usize>,
+        /// Per bag name, the possible games with the least slack first
+        pub(crate) closest_to_impossible: Vec<(String, Vec<GameSlack>)>,
+    }
+    impl Stats {
+        pub(crate) fn new(games: &[Game], bags: &[NamedBag]) -> Result<Self, String> {
+            Ok(Self {
+                num_games: games.len(),
+                colors: color_stats(games),
+                power_histogram: power_histogram(games)?,
+                turns_per_game: games.iter().fold(BTreeMap::new(), |mut counts, game| {
+                    *counts.entry(game.turns.len()).or_insert(0) += 1;
+                    counts
+                }),
+                closest_to_impossible: bags
+                    .iter()
+                    .map(|named_bag| {
+                        (
+                            named_bag.name.clone(),
+                            closest_to_impossible(games, named_bag),
+                        )
+                    })
+                    .collect(),
+            })
         }
-        CubeBag {
-            red_cubes: max_red_drawn,
-            green_cubes: max_green_drawn,
-            blue_cubes: max_blue_drawn,
+
+        pub(crate) fn format(&self, format: StatsFormat) -> String {
+            match format {
+                StatsFormat::Text => self.format_text(),
+                StatsFormat::Csv => self.format_csv(),
+            }
+        }
+
+        fn format_text(&self) -> String {
+            let mut text = format!("Games: {}\n\nCubes drawn per turn\n", self.num_games);
+            text.push_str(&format!(
+                "  {:<12} {:>5} {:>5} {:>8} {:>12}\n",
+                "COLOR", "MIN", "MAX", "MEAN", "TURNS DRAWN"
+            ));
+            for color_stats in &self.colors {
+                text.push_str(&format!(
+                    "  {:<12} {:>5} {:>5} {:>8.2} {:>12}\n",
+                    color_stats.color.to_string(),
+                    color_stats.min,
+                    color_stats.max,
+                    color_stats.mean,
+                    color_stats.turns_drawn_in
+                ));
+            }
+
+            text.push_str("\nPower of min cube bag\n");
+            let max_count = self
+                .power_histogram
+                .iter()
+                .map(|bucket| bucket.count)
+                .max()
+                .unwrap_or(0);
+            for bucket in &self.power_histogram {
+                let bar_len = (bucket.count * HISTOGRAM_BAR_WIDTH).div_ceil(max_count.max(1));
+                let row = format!(
+                    "  {:>17} {:>5} {}",
+                    format!("{}..={}", bucket.start, bucket.end),
+                    bucket.count,
+                    "#".repeat(bar_len)
+                );
+                text.push_str(row.trim_end());
+                text.push('\n');
+            }
+
+            text.push_str("\nTurns per game\n");
+            for (num_turns, num_games) in &self.turns_per_game {
+                text.push_str(&format!(
+                    "  {:>5} turns {:>5} games\n",
+                    num_turns, num_games
+                ));
+            }
+
+            for (bag_name, slacks) in &self.closest_to_impossible {
+                text.push_str(&format!("\nClosest to impossible with bag {}\n", bag_name));
+                for slack in slacks {
+                    text.push_str(&format!(
+                        "  Game {:<5} {} spare {}\n",
+                        slack.game_number, slack.slack, slack.tightest_color
+                    ));
+                }
+            }
+            text
+        }
+
+        /// One row per value, with fixed columns. Columns that don't apply to a
+        /// section are left empty.
+        fn format_csv(&self) -> String {
+            let mut csv = "section,bag,game,color,metric,value\n".to_string();
+            csv.push_str(&format!("games,,,,count,{}\n", self.num_games));
+            for color_stats in &self.colors {
+                let color = csv_field(&color_stats.color.to_string());
+                csv.push_str(&format!("color,,,{},min,{}\n", color, color_stats.min));
+                csv.push_str(&format!("color,,,{},max,{}\n", color, color_stats.max));
+                csv.push_str(&format!("color,,,{},mean,{}\n", color, color_stats.mean));
+                csv.push_str(&format!(
+                    "color,,,{},turns_drawn_in,{}\n",
+                    color, color_stats.turns_drawn_in
+                ));
+            }
+            for bucket in &self.power_histogram {
+                csv.push_str(&format!(
+                    "power_histogram,,,,games_with_power_{}..={},{}\n",
+                    bucket.start, bucket.end, bucket.count
+                ));
+            }
+            for (num_turns, num_games) in &self.turns_per_game {
+                csv.push_str(&format!(
+                    "turns_per_game,,,,games_with_{}_turns,{}\n",
+                    num_turns, num_games
+                ));
+            }
+            for (bag_name, slacks) in &self.closest_to_impossible {
+                for slack in slacks {
+                    csv.push_str(&format!(
+                        "closest_to_impossible,{},{},{},spare,{}\n",
+                        csv_field(bag_name),
+                        slack.game_number,
+                        csv_field(&slack.tightest_color.to_string()),
+                        slack.slack
+                    ));
+                }
+            }
+            csv
+        }
+    }
+
+    fn csv_field(value: &str) -> String {
+        if value.contains([',', '"', '\n']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+
+    fn color_stats(games: &[Game]) -> Vec<ColorStats> {
+        let turns: Vec<_> = games.iter().flat_map(|game| game.turns.iter()).collect();
+
+        colors_drawn(games)
+            .into_iter()
+            .map(|color| {
+                let drawn: Vec<u32> = turns
+                    .iter()
+                    .map(|turn| *turn.cubes_drawn.get(&color).unwrap_or(&0))
+                    .collect();
+                ColorStats {
+                    color: color.clone(),
+                    min: *drawn.iter().min().unwrap(),
+                    max: *drawn.iter().max().unwrap(),
+                    mean: drawn.iter().sum::<u32>() as f64 / drawn.len() as f64,
+                    turns_drawn_in: drawn.iter().filter(|num_drawn| **num_drawn > 0).count(),
+                }
+            })
+            .collect()
+    }
+
+    /// Splits the range of powers into equal width buckets
+    fn power_histogram(games: &[Game]) -> Result<Vec<HistogramBucket>, String> {
+        let colors = colors_drawn(games);
+        let powers: Vec<u64> = games
+            .iter()
+            .map(|game| game.min_cube_bag(&colors).power())
+            .collect::<Result<_, _>>()?;
+        let (Some(min_power), Some(max_power)) = (powers.iter().min(), powers.iter().max()) else {
+            return Ok(vec![]);
+        };
+
+        // Same as rounding (max - min + 1) / buckets up, without overflowing at u64::MAX
+        let bucket_width = (max_power - min_power) / NUM_POWER_HISTOGRAM_BUCKETS + 1;
+        let mut buckets: Vec<HistogramBucket> = (0..NUM_POWER_HISTOGRAM_BUCKETS)
+            .map_while(|bucket_idx| {
+                bucket_idx
+                    .checked_mul(bucket_width)
+                    .and_then(|offset| min_power.checked_add(offset))
+            })
+            .take_while(|start| start <= max_power)
+            .map(|start| HistogramBucket {
+                start,
+                end: start.saturating_add(bucket_width - 1),
+                count: 0,
+            })
+            .collect();
+        for power in &powers {
+            buckets[((power - min_power) / bucket_width) as usize].count += 1;
+        }
+        Ok(buckets)
+    }
+
+    fn closest_to_impossible(games: &[Game], named_bag: &NamedBag) -> Vec<GameSlack> {
+        let mut slacks: Vec<GameSlack> = games
+            .iter()
+            .filter(|game| game.is_possible_with_bag(&named_bag.bag))
+            .filter_map(|game| {
+                let min_cube_bag = game.min_cube_bag(&[]);
+                named_bag
+                    .bag
+                    .cubes
+                    .iter()
+                    .map(|(color, num_cubes)| (num_cubes - min_cube_bag.num_cubes(color), color))
+                    .min()
+                    .map(|(slack, color)| GameSlack {
+                        game_number: game.number,
+                        slack,
+                        tightest_color: color.clone(),
+                    })
+            })
+            .collect();
+        slacks.sort_by_key(|slack| (slack.slack, slack.game_number));
+        slacks.truncate(NUM_CLOSEST_TO_IMPOSSIBLE);
+        slacks
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::{GameSlack, HistogramBucket, Stats, StatsFormat};
+        use crate::{parse_game_from_str, CubeColor, Game, NamedBag};
+
+        fn get_games() -> Vec<Game> {
+            [
+                "Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green",
+                "Game 2: 1 blue, 2 green; 3 green, 4 blue, 1 red; 1 green, 1 blue",
+                "Game 3: 8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red",
+                "Game 4: 1 green, 3 red, 6 blue; 3 green, 6 red; 3 green, 15 blue, 14 red",
+                "Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green",
+            ]
+            .iter()
+            .map(|line| parse_game_from_str(line).unwrap())
+            .collect()
+        }
+
+        fn get_bags() -> Vec<NamedBag> {
+            vec!["default: 12 red, 13 green, 14 blue".parse().unwrap()]
+        }
+
+        #[test]
+        fn test_computes_stats() {
+            let stats = Stats::new(&get_games(), &get_bags()).unwrap();
+
+            assert_eq!(stats.num_games, 5);
+
+            let red = &stats.colors[2];
+            assert_eq!(red.color, CubeColor::from("red"));
+            assert_eq!((red.min, red.max, red.turns_drawn_in), (0, 20, 11));
+            assert!((red.mean - 61.0 / 14.0).abs() < 1e-9);
+
+            // Powers are 48, 12, 1560, 630 and 36
+            assert_eq!(stats.power_histogram.len(), 10);
+            assert_eq!(
+                stats.power_histogram[0],
+                HistogramBucket {
+                    start: 12,
+                    end: 166,
+                    count: 3
+                }
+            );
+            assert_eq!(stats.power_histogram[3].count, 1);
+            assert_eq!(stats.power_histogram[9].count, 1);
+
+            assert_eq!(stats.turns_per_game.get(&3), Some(&4));
+            assert_eq!(stats.turns_per_game.get(&2), Some(&1));
+
+            assert_eq!(
+                stats.closest_to_impossible[0].1[0],
+                GameSlack {
+                    game_number: 5,
+                    slack: 6,
+                    tightest_color: CubeColor::from("red"),
+                }
+            );
+            assert_eq!(
+                stats.closest_to_impossible[0]
+                    .1
+                    .iter()
+                    .map(|slack| slack.game_number)
+                    .collect::<Vec<_>>(),
+                vec![5, 1, 2]
+            );
+        }
+
+        #[test]
+        fn test_formats_stats_as_csv() {
+            let csv = Stats::new(&get_games(), &get_bags())
+                .unwrap()
+                .format(StatsFormat::Csv);
+            let mut lines = csv.lines();
+            assert_eq!(lines.next(), Some("section,bag,game,color,metric,value"));
+            assert!(csv.contains("\ncolor,,,red,max,20\n"));
+            assert!(csv.contains("\npower_histogram,,,,games_with_power_12..=166,3\n"));
+            assert!(csv.contains("\nturns_per_game,,,,games_with_3_turns,4\n"));
+            assert!(csv.contains("\nclosest_to_impossible,default,5,red,spare,6\n"));
+            assert!(lines.all(|line| line.split(',').count() == 6));
+        }
+
+        #[test]
+        fn test_formats_stats_as_text() {
+            let text = Stats::new(&get_games(), &get_bags())
+                .unwrap()
+                .format(StatsFormat::Text);
+            assert!(text.contains("  red              0    20     4.36           11\n"));
+            assert!(text
+                .contains("\nClosest to impossible with bag default\n  Game 5     6 spare red\n"));
         }
     }
 }
@@ -114,85 +1043,164 @@ fn turn_parse_error_msg(game_num: usize, turn_num: usize, msg: &str) -> String {
     format!("GAME {}, TURN {}: {}", game_num, turn_num + 1, msg)
 }
 
+/// A parse error and the byte range of the line it points at
+#[derive(Debug, PartialEq)]
+struct ParseError {
+    message: String,
+    span: Range<usize>,
+}
+impl ParseError {
+    fn new(span: Range<usize>, message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            span,
+        }
+    }
+
+    /// Compiler style report with the line and a caret underline below the span.
+    /// `line_num` starts at 0 like everywhere else, but is shown starting at 1.
+    fn render(&self, line_num: usize, line: &str) -> String {
+        let line_label = (line_num + 1).to_string();
+        let gutter = " ".repeat(line_label.len());
+        let col = line[..self.span.start].chars().count();
+        let underline_len = line[self.span.clone()].chars().count().max(1);
+        format!(
+            "error: {}\n{}--> stdin:{}:{}\n{} |\n{} | {}\n{} | {}{}\n",
+            self.message,
+            gutter,
+            line_label,
+            col + 1,
+            gutter,
+            line_label,
+            line,
+            gutter,
+            " ".repeat(col),
+            "^".repeat(underline_len),
+        )
+    }
+}
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Parses "<number> <color>", `offset` being where `cubes_drawn` starts in the line.
+/// Returns the color's span along with it.
 fn parse_num_cubes_and_color(
     game_number: usize,
     turn_number: usize,
+    offset: usize,
     cubes_drawn: &str,
-) -> Result<(u32, &str), String> {
+) -> Result<(u32, &str, Range<usize>), ParseError> {
     let (num_cubes, color) = cubes_drawn.split_once(' ').ok_or_else(|| {
-        turn_parse_error_msg(
-            game_number,
-            turn_number,
-            "Expected a space separating number of cubes and color",
+        ParseError::new(
+            offset..offset + cubes_drawn.len(),
+            turn_parse_error_msg(
+                game_number,
+                turn_number,
+                "Expected a space separating number of cubes and color",
+            ),
         )
     })?;
+    let color_start = offset + num_cubes.len() + 1;
+    let color_span = color_start..color_start + color.len();
 
     let num_cubes = num_cubes.parse::<u32>().map_err(|_| {
-        turn_parse_error_msg(
-            game_number,
-            turn_number,
-            &format!(
-                "Failed to parse number of cubes in turn for color {}",
-                color
+        ParseError::new(
+            offset..offset + num_cubes.len(),
+            turn_parse_error_msg(
+                game_number,
+                turn_number,
+                &format!(
+                    "Failed to parse number of cubes in turn for color {}",
+                    color
+                ),
             ),
         )
     })?;
 
-    Ok((num_cubes, color))
+    Ok((num_cubes, color, color_span))
 }
 
+/// `offset` is where `turn_to_parse` starts in the line, so errors can point into it
 fn parse_turn_from_str(
     game_number: usize,
     turn_number: usize,
+    offset: usize,
     turn_to_parse: &str,
-) -> Result<GameTurn, String> {
+) -> Result<GameTurn, ParseError> {
     let cubes_drawn_to_parse = turn_to_parse.split(", ");
     let mut turn = GameTurn::new();
 
+    let mut cubes_drawn_offset = offset;
     for cubes_drawn in cubes_drawn_to_parse {
-        let (num_cubes, color) = parse_num_cubes_and_color(game_number, turn_number, cubes_drawn)?;
-
-        if color == "red" {
-            turn = turn
-                .with_red(num_cubes)
-                .map_err(|msg| turn_parse_error_msg(game_number, turn_number, &msg))?;
-        } else if color == "green" {
-            turn = turn
-                .with_green(num_cubes)
-                .map_err(|msg| turn_parse_error_msg(game_number, turn_number, &msg))?;
-        } else if color == "blue" {
-            turn = turn
-                .with_blue(num_cubes)
-                .map_err(|msg| turn_parse_error_msg(game_number, turn_number, &msg))?;
-        } else {
-            return Err(format!(
-                "Expected color to be one of \"red\", \"green\", or \"blue\". Got {}",
-                color
+        let (num_cubes, color, color_span) =
+            parse_num_cubes_and_color(game_number, turn_number, cubes_drawn_offset, cubes_drawn)?;
+        cubes_drawn_offset += cubes_drawn.len() + ", ".len();
+
+        if color.is_empty() {
+            return Err(ParseError::new(
+                color_span,
+                turn_parse_error_msg(
+                    game_number,
+                    turn_number,
+                    "Expected a color after number of cubes",
+                ),
             ));
         }
+
+        check_color_name(color).map_err(|msg| {
+            ParseError::new(
+                color_span.clone(),
+                turn_parse_error_msg(game_number, turn_number, &msg),
+            )
+        })?;
+
+        turn = turn
+            .with_cubes(CubeColor::from(color), num_cubes)
+            .map_err(|msg| {
+                ParseError::new(
+                    color_span,
+                    turn_parse_error_msg(game_number, turn_number, &msg),
+                )
+            })?;
     }
     Ok(turn)
 }
 
-fn parse_game_from_str(game_str: &str) -> Result<Game, String> {
+fn parse_game_from_str(game_str: &str) -> Result<Game, ParseError> {
     let mut game_turns = Vec::new();
-    let (game_id, turns_to_parse) = game_str
-        .split_once(": ")
-        .ok_or("Expected a colon (:) separating game number and turns")?;
-    let (_, game_number) = game_id
-        .split_once(' ')
-        .ok_or("Expected a space separating game and number")?;
-    let game_number = game_number
-        .parse::<usize>()
-        .map_err(|_| "Failed to parse game number")?;
+    let (game_id, turns_to_parse) = game_str.split_once(": ").ok_or_else(|| {
+        ParseError::new(
+            0..game_str.len(),
+            "Expected a colon (:) separating game number and turns",
+        )
+    })?;
+    let (game_header, game_number) = game_id.split_once(' ').ok_or_else(|| {
+        ParseError::new(
+            0..game_id.len(),
+            "Expected a space separating game and number",
+        )
+    })?;
+    let game_number_start = game_header.len() + 1;
+    let game_number = game_number.parse::<usize>().map_err(|_| {
+        ParseError::new(
+            game_number_start..game_id.len(),
+            "Failed to parse game number",
+        )
+    })?;
 
     let turns_to_parse = turns_to_parse.split("; ");
+    let mut turn_offset = game_id.len() + ": ".len();
     for (turn_number, turn_to_parse) in turns_to_parse.enumerate() {
         game_turns.push(parse_turn_from_str(
             game_number,
             turn_number,
+            turn_offset,
             turn_to_parse,
         )?);
+        turn_offset += turn_to_parse.len() + "; ".len();
     }
 
     Ok(Game {
@@ -201,54 +1209,330 @@ fn parse_game_from_str(game_str: &str) -> Result<Game, String> {
     })
 }
 
-fn main() {
-    let mut sum_of_possible_games = 0;
-    let mut sum_of_min_cube_bag_powers = 0;
+#[derive(Debug, PartialEq)]
+struct NamedBag {
+    name: String,
+    bag: CubeBag,
+}
+impl FromStr for NamedBag {
+    type Err = String;
 
-    let bag = CubeBag {
-        red_cubes: 12,
-        green_cubes: 13,
-        blue_cubes: 14,
-    };
+    /// Parses "<name>: <bag>", e.g. "default: 12 red, 13 green, 14 blue"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, bag) = s
+            .split_once(':')
+            .ok_or("Expected a colon (:) separating bag name and cubes")?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Expected a bag name before the colon (:)".to_string());
+        }
+        Ok(NamedBag {
+            name: name.to_string(),
+            bag: bag.trim().parse()?,
+        })
+    }
+}
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Report {
+    /// Sum of the numbers of the games each bag makes possible
+    PossibleSum,
+    /// The numbers of the games each bag makes possible
+    PossibleGames,
+    /// Why each bag rejects every game it makes impossible
+    Explain,
+    /// The most likely bag for each game, and how well each bag explains it
+    Estimate,
+    /// Sum of the powers of every game's min cube bag, which doesn't depend on the bags
+    PowerSum,
+    /// Statistics over the whole log, in the configured `StatsFormat`
+    Stats,
+}
+impl FromStr for Report {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "possible-sum" => Ok(Report::PossibleSum),
+            "possible-games" => Ok(Report::PossibleGames),
+            "explain" => Ok(Report::Explain),
+            "estimate" => Ok(Report::Estimate),
+            "power-sum" => Ok(Report::PowerSum),
+            "stats" => Ok(Report::Stats),
+            _ => Err(format!(
+                "Unknown report \"{}\", expected one of \"possible-sum\", \"possible-games\", \"explain\", \"estimate\", \"power-sum\" or \"stats\"",
+                s
+            )),
+        }
+    }
+}
+
+/// Which bags to check the games against and what to report.
+/// Built from a config file and/or command line arguments.
+#[derive(Debug, PartialEq, Default)]
+struct Config {
+    bags: Vec<NamedBag>,
+    reports: Vec<Report>,
+    stats_format: Option<StatsFormat>,
+}
+impl Config {
+    fn add_bag(&mut self, bag: NamedBag) -> Result<(), String> {
+        if self.bags.iter().any(|existing| existing.name == bag.name) {
+            return Err(format!("Bag \"{}\" defined more than once", bag.name));
+        }
+        self.bags.push(bag);
+        Ok(())
+    }
+
+    fn add_report(&mut self, report: Report) {
+        if !self.reports.contains(&report) {
+            self.reports.push(report);
+        }
+    }
+
+    /// The puzzle's 12 red, 13 green, 14 blue bag, its two answers and the
+    /// explanations, for anything the config and arguments left unset
+    fn with_defaults(mut self) -> Self {
+        if self.bags.is_empty() {
+            self.bags
+                .push("default: 12 red, 13 green, 14 blue".parse().unwrap());
+        }
+        if self.reports.is_empty() {
+            self.reports = vec![Report::PossibleSum, Report::PowerSum, Report::Explain];
+        }
+        self
+    }
+}
+impl FromStr for Config {
+    type Err = String;
+
+    /// Parses a config file with one entry per line, either `bag <name>: <bag>`,
+    /// `report <report>` or `format <text|csv>`. Blank lines and lines starting
+    /// with `#` are ignored.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Config::default();
+        for (line_num, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let with_line = |e: String| format!("LINE {}: {}", line_num, e);
+            match line.split_once(' ') {
+                Some(("bag", bag)) => config
+                    .add_bag(bag.parse().map_err(with_line)?)
+                    .map_err(with_line)?,
+                Some(("report", report)) => {
+                    config.add_report(report.trim().parse().map_err(with_line)?)
+                }
+                Some(("format", format)) => {
+                    config.stats_format = Some(format.trim().parse().map_err(with_line)?)
+                }
+                _ => {
+                    return Err(with_line(format!(
+                        "Expected line to start with \"bag\", \"report\" or \"format\", got \"{}\"",
+                        line
+                    )))
+                }
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Usage: `day-02 [--config <FILE>] [--bag "<name>: <bag>"]... [--report <report>]... [--format <text|csv>]`
+/// Bags and reports from arguments are added to those from the config file.
+/// `--format` picks how the stats report is printed.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Config, String> {
+    let mut config = Config::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                let path = args.next().ok_or("Expected a file path after --config")?;
+                let file_config = std::fs::read_to_string(&path)
+                    .map_err(|e| format!("Failed to read config file {}: {}", path, e))?
+                    .parse::<Config>()
+                    .map_err(|e| format!("Failed to parse config file {}: {}", path, e))?;
+                for bag in file_config.bags {
+                    config.add_bag(bag)?;
+                }
+                for report in file_config.reports {
+                    config.add_report(report);
+                }
+                config.stats_format = file_config.stats_format.or(config.stats_format);
+            }
+            "--format" => {
+                config.stats_format = Some(
+                    args.next()
+                        .ok_or("Expected a format after --format")?
+                        .parse()?,
+                )
+            }
+            "--bag" => config.add_bag(args.next().ok_or("Expected a bag after --bag")?.parse()?)?,
+            "--report" => config.add_report(
+                args.next()
+                    .ok_or("Expected a report after --report")?
+                    .parse()?,
+            ),
+            _ => return Err(format!("Unknown argument \"{}\"", arg)),
+        }
+    }
+    Ok(config.with_defaults())
+}
+
+fn possible_game_numbers(games: &[Game], bag: &CubeBag) -> Vec<usize> {
+    games
+        .iter()
+        .filter(|game| game.is_possible_with_bag(bag))
+        .map(|game| game.number)
+        .collect()
+}
+
+fn main() {
+    let config = parse_args(std::env::args().skip(1)).unwrap_or_else(|e| panic!("{}", e));
+
+    let mut games = vec![];
     for (line_num, line_res) in std::io::stdin().lines().enumerate() {
         let line = line_res.unwrap_or_else(|_| panic!("LINE {}: Failed to read line!", line_num));
-        let game = parse_game_from_str(&line)
-            .unwrap_or_else(|_| panic!("LINE {}: Failed to parse line!", line_num));
+        match parse_game_from_str(&line) {
+            Ok(game) => games.push(game),
+            Err(e) => {
+                eprint!("{}", e.render(line_num, &line));
+                std::process::exit(1);
+            }
+        }
+    }
 
-        if game.is_possible_with_bag(&bag) {
-            sum_of_possible_games += game.number;
+    let bag_reports: Vec<Report> = config
+        .reports
+        .iter()
+        .copied()
+        .filter(|report| {
+            matches!(
+                report,
+                Report::PossibleSum | Report::PossibleGames | Report::Explain
+            )
+        })
+        .collect();
+    if !bag_reports.is_empty() {
+        for named_bag in &config.bags {
+            let possible_games = possible_game_numbers(&games, &named_bag.bag);
+
+            println!("Bag {} ({}):", named_bag.name, named_bag.bag);
+            for report in &bag_reports {
+                match report {
+                    Report::PossibleSum => println!(
+                        "  Sum of possible games: {}",
+                        possible_games.iter().sum::<usize>()
+                    ),
+                    Report::PossibleGames => {
+                        let game_numbers: Vec<String> =
+                            possible_games.iter().map(|n| n.to_string()).collect();
+                        println!("  Possible games: {}", game_numbers.join(", "));
+                    }
+                    Report::Explain => {
+                        for game in &games {
+                            let violations = game.violations_with_bag(&named_bag.bag);
+                            if violations.is_empty() {
+                                continue;
+                            }
+                            println!("  Game {} is impossible:", game.number);
+                            for violation in violations {
+                                println!("    {}", violation);
+                            }
+                        }
+                    }
+                    Report::PowerSum | Report::Estimate | Report::Stats => unreachable!(),
+                }
+            }
         }
-        sum_of_min_cube_bag_powers += game.min_cube_bag().power();
     }
 
-    println!("Sum of possible games: {}", sum_of_possible_games);
-    println!(
-        "Sum of powers of min cube bags: {}",
-        sum_of_min_cube_bag_powers,
-    );
+    if config.reports.contains(&Report::Estimate) {
+        let bags: Vec<&CubeBag> = config.bags.iter().map(|named_bag| &named_bag.bag).collect();
+        let print_estimate = |title: String, turns: Vec<&GameTurn>| {
+            match estimate::estimate_bag(turns.iter().copied()) {
+                Ok(estimate) => {
+                    println!("{} most likely bag:", title);
+                    for color_estimate in &estimate.colors {
+                        println!("  {}", color_estimate);
+                    }
+                }
+                Err(e) => println!("{} most likely bag: {}", title, e),
+            }
+            match estimate::rank_bags(turns.iter().copied(), &bags) {
+                Ok(ranked) => {
+                    let ranked: Vec<String> = ranked
+                        .into_iter()
+                        .map(|(bag_idx, log_likelihood)| {
+                            format!("{} ({:.2})", config.bags[bag_idx].name, log_likelihood)
+                        })
+                        .collect();
+                    println!("  Bags by log-likelihood: {}", ranked.join(", "));
+                }
+                Err(e) => println!("  Bags by log-likelihood: {}", e),
+            }
+        };
+
+        for game in &games {
+            print_estimate(format!("Game {}", game.number), game.turns.iter().collect());
+        }
+        print_estimate(
+            "All games (one shared bag)".to_string(),
+            games.iter().flat_map(|game| game.turns.iter()).collect(),
+        );
+    }
+
+    if config.reports.contains(&Report::PowerSum) {
+        match sum_of_min_cube_bag_powers(&games) {
+            Ok(sum) => println!("Sum of powers of min cube bags: {}", sum),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if config.reports.contains(&Report::Stats) {
+        match Stats::new(&games, &config.bags) {
+            Ok(stats) => print!("{}", stats.format(config.stats_format.unwrap_or_default())),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use std::collections::HashMap;
 
-    use crate::{CubeColor, Game, GameTurn};
+    use crate::{
+        Config, CubeBag, CubeColor, Game, GameTurn, NamedBag, ParseError, Report, StatsFormat,
+        Violation,
+    };
 
     fn game_turn(red_cubes: u32, green_cubes: u32, blue_cubes: u32) -> GameTurn {
         let mut cubes_drawn = HashMap::new();
         if red_cubes > 0 {
-            cubes_drawn.insert(CubeColor::Red, red_cubes);
+            cubes_drawn.insert(CubeColor::from("red"), red_cubes);
         }
         if green_cubes > 0 {
-            cubes_drawn.insert(CubeColor::Green, green_cubes);
+            cubes_drawn.insert(CubeColor::from("green"), green_cubes);
         }
         if blue_cubes > 0 {
-            cubes_drawn.insert(CubeColor::Blue, blue_cubes);
+            cubes_drawn.insert(CubeColor::from("blue"), blue_cubes);
         }
         GameTurn { cubes_drawn }
     }
 
+    fn cube_bag(cubes: &[(&str, u32)]) -> CubeBag {
+        cubes
+            .iter()
+            .map(|(color, num_cubes)| (CubeColor::from(*color), *num_cubes))
+            .collect()
+    }
+
     fn get_test_cases() -> Vec<(String, Game)> {
         vec![
             (
@@ -308,11 +1592,7 @@ mod test {
             .map(|(input_line, _)| input_line.to_string())
             .collect();
 
-        let bag = super::CubeBag {
-            red_cubes: 12,
-            green_cubes: 13,
-            blue_cubes: 14,
-        };
+        let bag = cube_bag(&[("red", 12), ("green", 13), ("blue", 14)]);
 
         let mut sum = 0;
         for input_line in input_lines {
@@ -331,11 +1611,283 @@ mod test {
             .map(|(input_line, _)| input_line.to_string())
             .collect();
 
-        let mut sum = 0;
-        for input_line in input_lines {
-            let game = super::parse_game_from_str(&input_line).unwrap();
-            sum += game.min_cube_bag().power();
+        let games: Vec<Game> = input_lines
+            .iter()
+            .map(|input_line| super::parse_game_from_str(input_line).unwrap())
+            .collect();
+        assert_eq!(super::sum_of_min_cube_bag_powers(&games), Ok(2286));
+    }
+
+    #[test]
+    fn test_power_counts_colors_a_game_did_not_draw() {
+        let games: Vec<Game> = ["Game 1: 3 red, 2 green", "Game 2: 1 red; 4 blue"]
+            .iter()
+            .map(|line| super::parse_game_from_str(line).unwrap())
+            .collect();
+        let colors = super::colors_drawn(&games);
+
+        assert_eq!(
+            games[0].min_cube_bag(&colors),
+            cube_bag(&[("red", 3), ("green", 2), ("blue", 0)])
+        );
+        assert_eq!(games[0].min_cube_bag(&colors).power(), Ok(0));
+        assert_eq!(super::sum_of_min_cube_bag_powers(&games[..1]), Ok(6));
+        assert_eq!(super::sum_of_min_cube_bag_powers(&games), Ok(0));
+    }
+
+    #[test]
+    fn test_power_reports_overflow() {
+        let game =
+            super::parse_game_from_str("Game 1: 100000 a, 100000 b, 100000 c, 100000 d").unwrap();
+        assert_eq!(
+            super::sum_of_min_cube_bag_powers(&[game]),
+            Err("GAME 1: Power of bag overflowed u64".to_string())
+        );
+    }
+
+    #[test]
+    fn test_supports_any_set_of_colors() {
+        let game = super::parse_game_from_str(
+            "Game 7: 2 teal, 1 dark red; 5 teal, 3 purple; 1 purple, 2 dark red",
+        )
+        .unwrap();
+
+        assert_eq!(
+            game.min_cube_bag(&[]),
+            cube_bag(&[("teal", 5), ("dark red", 2), ("purple", 3)])
+        );
+        assert_eq!(game.min_cube_bag(&[]).power(), Ok(30));
+        assert!(game.is_possible_with_bag(&cube_bag(&[
+            ("teal", 5),
+            ("dark red", 2),
+            ("purple", 3)
+        ])));
+        assert!(!game.is_possible_with_bag(&cube_bag(&[("teal", 5), ("dark red", 2)])));
+        assert!(!game.is_possible_with_bag(&cube_bag(&[("red", 12), ("green", 13), ("blue", 14)])));
+    }
+
+    #[test]
+    fn test_rejects_repeated_color_in_turn() {
+        assert_eq!(
+            super::parse_game_from_str("Game 2: 1 teal, 2 red; 3 teal, 4 teal"),
+            Err(ParseError::new(
+                33..37,
+                "GAME 2, TURN 2: Already set number of teal cubes drawn this turn"
+            ))
+        );
+        assert!(super::parse_game_from_str("Game 2: 1 teal, 2 red, 3 red").is_err());
+    }
+
+    #[test]
+    fn test_rejects_spaces_around_color() {
+        assert_eq!(
+            super::parse_game_from_str("Game 3: 3  red"),
+            Err(ParseError::new(
+                10..14,
+                "GAME 3, TURN 1: Color \" red\" has leading or trailing spaces"
+            ))
+        );
+        assert!(super::parse_game_from_str("Game 3: 3 red , 1 blue").is_err());
+        assert_eq!(
+            "2 r3d".parse::<CubeBag>(),
+            Err("Expected color to only contain letters and spaces, got \"r3d\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parses_config() {
+        let config = "\
+# Candidate bags
+bag puzzle: 12 red, 13 green, 14 blue
+bag tiny: 1 red, 2 green, 6 blue
+
+report possible-games
+report power-sum
+format csv
+"
+        .parse::<Config>()
+        .unwrap();
+
+        assert_eq!(
+            config,
+            Config {
+                bags: vec![
+                    NamedBag {
+                        name: "puzzle".to_string(),
+                        bag: cube_bag(&[("red", 12), ("green", 13), ("blue", 14)]),
+                    },
+                    NamedBag {
+                        name: "tiny".to_string(),
+                        bag: cube_bag(&[("red", 1), ("green", 2), ("blue", 6)]),
+                    },
+                ],
+                reports: vec![Report::PossibleGames, Report::PowerSum],
+                stats_format: Some(StatsFormat::Csv),
+            }
+        );
+
+        assert!("bag a: 1 red\nbag a: 2 red".parse::<Config>().is_err());
+        assert!("bag a: 1 red, 2 red".parse::<Config>().is_err());
+        assert!("bag: 1 red".parse::<Config>().is_err());
+        assert!("report everything".parse::<Config>().is_err());
+        assert!("bags a: 1 red".parse::<Config>().is_err());
+        assert_eq!(
+            "bag a: 12  red".parse::<Config>(),
+            Err("LINE 0: Color \" red\" has leading or trailing spaces".to_string())
+        );
+    }
+
+    #[test]
+    fn test_args_add_to_defaults() {
+        let config = super::parse_args(std::iter::empty()).unwrap();
+        assert_eq!(config.bags.len(), 1);
+        assert_eq!(
+            config.reports,
+            vec![Report::PossibleSum, Report::PowerSum, Report::Explain]
+        );
+
+        let config = super::parse_args(
+            ["--bag", "tiny: 1 red", "--report", "possible-games"]
+                .into_iter()
+                .map(String::from),
+        )
+        .unwrap();
+        assert_eq!(config.bags[0].name, "tiny");
+        assert_eq!(config.reports, vec![Report::PossibleGames]);
+    }
+
+    #[test]
+    fn test_finds_possible_games_per_bag() {
+        let games: Vec<Game> = get_test_cases().into_iter().map(|(_, game)| game).collect();
+
+        assert_eq!(
+            super::possible_game_numbers(
+                &games,
+                &cube_bag(&[("red", 12), ("green", 13), ("blue", 14)])
+            ),
+            vec![1, 2, 5]
+        );
+        assert_eq!(
+            super::possible_game_numbers(
+                &games,
+                &cube_bag(&[("red", 20), ("green", 13), ("blue", 15)])
+            ),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            super::possible_game_numbers(
+                &games,
+                &cube_bag(&[("red", 6), ("green", 3), ("blue", 6)])
+            ),
+            vec![1, 2, 5]
+        );
+        assert!(super::possible_game_numbers(&games, &cube_bag(&[])).is_empty());
+    }
+
+    #[test]
+    fn test_explains_impossible_games() {
+        let bag = cube_bag(&[("red", 12), ("green", 13), ("blue", 14)]);
+        let games: Vec<Game> = get_test_cases().into_iter().map(|(_, game)| game).collect();
+
+        assert!(games[0].violations_with_bag(&bag).is_empty());
+        assert_eq!(
+            games[2].violations_with_bag(&bag),
+            vec![Violation {
+                turn_index: 0,
+                color: CubeColor::from("red"),
+                num_drawn: 20,
+                bag_limit: 12,
+            }]
+        );
+        assert_eq!(
+            games[3].violations_with_bag(&bag),
+            vec![
+                Violation {
+                    turn_index: 2,
+                    color: CubeColor::from("blue"),
+                    num_drawn: 15,
+                    bag_limit: 14,
+                },
+                Violation {
+                    turn_index: 2,
+                    color: CubeColor::from("red"),
+                    num_drawn: 14,
+                    bag_limit: 12,
+                }
+            ]
+        );
+
+        let violations = games[3].violations_with_bag(&cube_bag(&[("red", 5)]));
+        assert_eq!(
+            violations
+                .iter()
+                .map(|violation| violation.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "TURN 1: drew 6 blue but the bag only has 0",
+                "TURN 1: drew 1 green but the bag only has 0",
+                "TURN 2: drew 3 green but the bag only has 0",
+                "TURN 2: drew 6 red but the bag only has 5",
+                "TURN 3: drew 15 blue but the bag only has 0",
+                "TURN 3: drew 3 green but the bag only has 0",
+                "TURN 3: drew 14 red but the bag only has 5",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_point_at_offending_token() {
+        let test_cases = [
+            ("Game 3: 8 green, 6 blue, 20 re$d; 5 blue", "re$d"),
+            ("Game 3: 8 green, 6 blue; 5 blue, x red", "x"),
+            ("Game 3: 8 green; 6 blue; 5 blue, -1 red", "-1"),
+            ("Game 3: 8 green, 6 blue, 2 green", "green"),
+            ("Game three: 8 green", "three"),
+            ("Game 3: 8 green, 6blue", "6blue"),
+            ("Game 3 8 green", "Game 3 8 green"),
+        ];
+
+        for (line, expected_token) in test_cases {
+            let e = super::parse_game_from_str(line).unwrap_err();
+            assert_eq!(
+                &line[e.span.clone()],
+                expected_token,
+                "Wrong span for error \"{}\" in LINE '{}'",
+                e,
+                line
+            );
         }
-        assert_eq!(2286, sum);
+
+        let e = super::parse_game_from_str("Game 3: 8 green, 6 blue, 2 green").unwrap_err();
+        assert_eq!(e.span, 27..32);
+    }
+
+    #[test]
+    fn test_renders_parse_error_with_caret_underline() {
+        let line = "Game 12: 3 blue; 4 réd, x green";
+        let e = super::parse_game_from_str(line).unwrap_err();
+        assert_eq!(
+            e.render(11, line),
+            "\
+error: GAME 12, TURN 2: Failed to parse number of cubes in turn for color green
+  --> stdin:12:25
+   |
+12 | Game 12: 3 blue; 4 réd, x green
+   |                         ^
+"
+        );
+
+        let line = "Game 1: 3 bl_ue";
+        let e = super::parse_game_from_str(line).unwrap_err();
+        assert_eq!(
+            e.render(0, line),
+            "\
+error: GAME 1, TURN 1: Expected color to only contain letters and spaces, got \"bl_ue\"
+ --> stdin:1:11
+  |
+1 | Game 1: 3 bl_ue
+  |           ^^^^^
+"
+        );
     }
 }
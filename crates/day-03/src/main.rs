@@ -1,3 +1,74 @@
+use std::{
+    collections::VecDeque,
+    io::Write,
+    ops::Range,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use graph::{AdjacencyGraph, GraphFormat};
+use render::{annotate_lines, render, RenderFormat};
+use schematic::{answer_query, Schematic, QUERY_HELP};
+
+/// Which cells around a number count as touching it
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Adjacency {
+    /// Directly up, down, left or right of one of the number's digits
+    Orthogonal,
+    /// Within this many cells of one of the number's digits in any direction,
+    /// including diagonally. A radius of 1 is the usual 8 neighbours.
+    Radius(usize),
+}
+impl Adjacency {
+    /// How many lines above or below a number a touching symbol can be
+    fn line_reach(&self) -> usize {
+        match self {
+            Adjacency::Orthogonal => 1,
+            Adjacency::Radius(radius) => *radius,
+        }
+    }
+
+    /// How many columns before or after a number a touching symbol can be
+    fn column_reach(&self) -> usize {
+        match self {
+            Adjacency::Orthogonal => 1,
+            Adjacency::Radius(radius) => *radius,
+        }
+    }
+}
+impl Default for Adjacency {
+    fn default() -> Self {
+        Adjacency::Radius(1)
+    }
+}
+impl FromStr for Adjacency {
+    type Err = String;
+
+    /// "4" for orthogonal, "8" for radius 1, or "radius:<N>" with N at least 1
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "4" => Ok(Adjacency::Orthogonal),
+            "8" => Ok(Adjacency::Radius(1)),
+            _ => {
+                let radius = s
+                    .strip_prefix("radius:")
+                    .and_then(|radius| radius.parse::<usize>().ok())
+                    .ok_or_else(|| {
+                        format!(
+                            "Expected adjacency \"4\", \"8\" or \"radius:<N>\", got \"{}\"",
+                            s
+                        )
+                    })?;
+                if radius == 0 {
+                    return Err("Adjacency radius must be at least 1".to_string());
+                }
+                Ok(Adjacency::Radius(radius))
+            }
+        }
+    }
+}
+
 #[derive(PartialEq, Debug)]
 struct NumberNode {
     value: u32,
@@ -6,152 +77,401 @@ struct NumberNode {
     y: usize,
 }
 impl NumberNode {
-    fn adjacency_bounds(&self) -> (usize, usize, usize, usize) {
+    fn adjacency_bounds(&self, radius: usize) -> (usize, usize, usize, usize) {
         (
-            self.start_x.saturating_sub(1),
-            self.end_x + 1,
-            self.y.saturating_sub(1),
-            self.y + 1,
+            self.start_x.saturating_sub(radius),
+            self.end_x + radius,
+            self.y.saturating_sub(radius),
+            self.y + radius,
         )
     }
-    fn is_adjacent_to(&self, symbol: &SymbolNode) -> bool {
-        let (min_x, max_x, min_y, max_y) = self.adjacency_bounds();
-        symbol.x >= min_x && symbol.x <= max_x && symbol.y >= min_y && symbol.y <= max_y
+    fn is_adjacent_to(&self, symbol: &SymbolNode, adjacency: Adjacency) -> bool {
+        match adjacency {
+            Adjacency::Orthogonal => {
+                let same_line_and_touching_end = symbol.y == self.y
+                    && (symbol.x + 1 == self.start_x || symbol.x == self.end_x + 1);
+                let line_above_or_below_a_digit = (symbol.y + 1 == self.y
+                    || symbol.y == self.y + 1)
+                    && symbol.x >= self.start_x
+                    && symbol.x <= self.end_x;
+                same_line_and_touching_end || line_above_or_below_a_digit
+            }
+            Adjacency::Radius(radius) => {
+                let (min_x, max_x, min_y, max_y) = self.adjacency_bounds(radius);
+                symbol.x >= min_x && symbol.x <= max_x && symbol.y >= min_y && symbol.y <= max_y
+            }
+        }
     }
 }
 
-#[derive(PartialEq, Debug, Eq)]
+#[derive(PartialEq, Debug, Eq, Clone, Copy)]
 enum SymbolType {
-    Gear,
+    /// A gear when exactly this many numbers are adjacent to it
+    Gear(usize),
     Other,
 }
 
 #[derive(PartialEq, Debug)]
 struct SymbolNode {
     sym_type: SymbolType,
+    /// The character the symbol was parsed from
+    c: char,
     x: usize,
     y: usize,
 }
 
+/// Symbols and numbers are kept in column order, as the parser finds them, so
+/// they can be looked up by column with a binary search
 #[derive(PartialEq, Debug)]
 struct Line {
     symbols: Vec<SymbolNode>,
     numbers: Vec<NumberNode>,
 }
+impl Line {
+    /// Symbols in columns `min_x..=max_x`
+    fn symbols_in_columns(&self, min_x: usize, max_x: usize) -> &[SymbolNode] {
+        let start = self.symbols.partition_point(|symbol| symbol.x < min_x);
+        let end = self.symbols.partition_point(|symbol| symbol.x <= max_x);
+        &self.symbols[start..end.max(start)]
+    }
+
+    /// Numbers with at least one digit in columns `min_x..=max_x`.
+    /// Numbers on a line never overlap, so their ends are in order too.
+    fn numbers_in_columns(&self, min_x: usize, max_x: usize) -> &[NumberNode] {
+        let start = self.numbers.partition_point(|number| number.end_x < min_x);
+        let end = self
+            .numbers
+            .partition_point(|number| number.start_x <= max_x);
+        &self.numbers[start..end.max(start)]
+    }
+}
 
 #[derive(PartialEq, Eq, Debug, Hash)]
 struct PartNumber(u32);
 #[derive(PartialEq, Eq, Debug, Hash)]
-struct GearRatio(u32);
+struct GearRatio(u64);
+
+/// Whether any symbol on the nearby lines touches the number.
+/// For each nearby line we binary search for the symbols in the columns the
+/// number can reach, then check those with the configured adjacency
+fn is_part_number(number: &NumberNode, nearby_lines: &[&Line], adjacency: Adjacency) -> bool {
+    let reach = adjacency.column_reach();
+
+    nearby_lines.iter().any(|line| {
+        line.symbols_in_columns(number.start_x.saturating_sub(reach), number.end_x + reach)
+            .iter()
+            .any(|symbol| number.is_adjacent_to(symbol, adjacency))
+    })
+}
+
+/// The gear ratio of a symbol, which is the product of its adjacent numbers when
+/// it is a `SymbolType::Gear` with exactly as many adjacent numbers as it requires.
+/// Nearby numbers are looked up by column the same way as in `is_part_number`
+fn gear_ratio(
+    symbol: &SymbolNode,
+    nearby_lines: &[&Line],
+    adjacency: Adjacency,
+) -> Option<GearRatio> {
+    let SymbolType::Gear(required_numbers) = symbol.sym_type else {
+        return None;
+    };
+    let reach = adjacency.column_reach();
+
+    let adjacent_numbers: Vec<&NumberNode> = nearby_lines
+        .iter()
+        .flat_map(|line| line.numbers_in_columns(symbol.x.saturating_sub(reach), symbol.x + reach))
+        .filter(|number| number.is_adjacent_to(symbol, adjacency))
+        .collect();
+
+    (adjacent_numbers.len() == required_numbers).then(|| {
+        GearRatio(
+            adjacent_numbers
+                .iter()
+                .map(|number| number.value as u64)
+                .product(),
+        )
+    })
+}
 
 /// Find all part numbers in a line.
-/// We only need the symbols that are on the current, prev & next lines (if they exist)
+/// We only need the symbols that are on lines within reach of the current line
 /// to determine if any numbers in this line are part numbers instead of comparing
 /// all numbers to all symbols across the entire input.
-/// We take the list of numbers and check if any are adjacent to any of the symbols
-/// either up, down, left, or right or diagonally
 fn find_part_numbers(
-    prev_line: Option<&Line>,
     current_line: &Line,
-    next_line: Option<&Line>,
+    nearby_lines: &[&Line],
+    adjacency: Adjacency,
 ) -> Vec<PartNumber> {
-    let search_symbols: Vec<&SymbolNode> = prev_line
-        .map(|l| l.symbols.iter())
-        .unwrap_or_default()
-        .chain(current_line.symbols.iter())
-        .chain(next_line.map(|l| l.symbols.iter()).unwrap_or_default())
-        .collect();
-
     current_line
         .numbers
         .iter()
-        .filter(|number| {
-            search_symbols
-                .iter()
-                .filter(|symbol| number.is_adjacent_to(symbol))
-                .count()
-                > 0
-        })
+        .filter(|number| is_part_number(number, nearby_lines, adjacency))
         .map(|number| PartNumber(number.value))
         .collect()
 }
 
+/// Find the gear ratio of every gear in a line
 fn find_gear_ratios(
-    prev_line: Option<&Line>,
     current_line: &Line,
-    next_line: Option<&Line>,
+    nearby_lines: &[&Line],
+    adjacency: Adjacency,
 ) -> Vec<GearRatio> {
-    let search_numbers: Vec<&NumberNode> = prev_line
-        .map(|l| l.numbers.iter())
-        .unwrap_or_default()
-        .chain(current_line.numbers.iter())
-        .chain(next_line.map(|l| l.numbers.iter()).unwrap_or_default())
-        .collect();
-
-    let gear_symbols = current_line
+    current_line
         .symbols
         .iter()
-        .filter(|s| s.sym_type == SymbolType::Gear);
+        .filter_map(|symbol| gear_ratio(symbol, nearby_lines, adjacency))
+        .collect()
+}
+
+/// Indices of the line at `y` and every line within the adjacency's reach above
+/// and below it, out of `line_count` lines
+fn nearby_line_indices(y: usize, line_count: usize, adjacency: Adjacency) -> Range<usize> {
+    let reach = adjacency.line_reach();
+    y.saturating_sub(reach)..(y + reach + 1).min(line_count)
+}
+
+/// The line at `y` and every line within the adjacency's reach above and below it
+fn nearby_lines(lines: &[Line], y: usize, adjacency: Adjacency) -> Vec<&Line> {
+    lines[nearby_line_indices(y, lines.len(), adjacency)]
+        .iter()
+        .collect()
+}
 
+/// Slides a window over the lines holding the current line and every line
+/// within the adjacency's reach above and below it
+fn find_all_part_numbers_and_gear_ratios(
+    mut lines: impl Iterator<Item = Line>,
+    adjacency: Adjacency,
+) -> (Vec<PartNumber>, Vec<GearRatio>) {
+    let reach = adjacency.line_reach();
+    let mut window: VecDeque<Line> = lines.by_ref().take(reach + 1).collect();
+    let mut current_idx = 0;
+
+    let mut part_numbers = vec![];
     let mut gear_ratios = vec![];
-    for gear_sym in gear_symbols {
-        let adjacent_numbers: Vec<&&NumberNode> = search_numbers
-            .iter()
-            .filter(|number| number.is_adjacent_to(gear_sym))
-            .collect();
 
-        if adjacent_numbers.len() == 2 {
-            gear_ratios.push(GearRatio(
-                adjacent_numbers[0].value * adjacent_numbers[1].value,
-            ));
+    while current_idx < window.len() {
+        let nearby_lines: Vec<&Line> = window.iter().collect();
+        part_numbers.extend(find_part_numbers(
+            &window[current_idx],
+            &nearby_lines,
+            adjacency,
+        ));
+        gear_ratios.extend(find_gear_ratios(
+            &window[current_idx],
+            &nearby_lines,
+            adjacency,
+        ));
+
+        if let Some(line) = lines.next() {
+            window.push_back(line);
+        }
+        if current_idx < reach {
+            current_idx += 1;
+        } else {
+            window.pop_front();
         }
     }
 
-    gear_ratios
+    (part_numbers, gear_ratios)
 }
 
-fn find_all_part_numbers_and_gear_ratios(
-    mut lines: impl Iterator<Item = Line>,
+/// Rows per band when processing a schematic in parallel
+const DEFAULT_BAND_ROWS: usize = 4096;
+
+/// Runs `job` for every band index on a pool of `threads` workers, which take the
+/// next unclaimed band until none are left. Results come back in band order.
+fn run_on_thread_pool<T: Send>(
+    band_count: usize,
+    threads: usize,
+    job: impl Fn(usize) -> T + Sync,
+) -> Vec<T> {
+    let next_band = AtomicUsize::new(0);
+    let mut results: Vec<(usize, T)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.clamp(1, band_count.max(1)))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = vec![];
+                    loop {
+                        let band = next_band.fetch_add(1, Ordering::Relaxed);
+                        if band >= band_count {
+                            return done;
+                        }
+                        done.push((band, job(band)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+    results.sort_by_key(|(band, _)| *band);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Parses the rows in bands on a thread pool
+fn parse_lines_in_parallel(
+    rows: &[String],
+    rules: &SymbolRules,
+    threads: usize,
+    band_rows: usize,
+) -> Vec<Line> {
+    let band_rows = band_rows.max(1);
+    run_on_thread_pool(rows.len().div_ceil(band_rows), threads, |band| {
+        let start = band * band_rows;
+        rows[start..(start + band_rows).min(rows.len())]
+            .iter()
+            .enumerate()
+            .map(|(offset, row)| parse_line_into_symbols_and_numbers(row, start + offset, rules))
+            .collect::<Vec<Line>>()
+    })
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Splits the lines into bands of `band_rows` rows and processes the bands on a
+/// thread pool. Each band can see a halo of the lines within reach above and below
+/// it, but only looks for part numbers and gears on its own rows, so every row is
+/// counted by exactly one band. Results are merged in band order, which gives the
+/// same output as `find_all_part_numbers_and_gear_ratios`
+fn find_all_part_numbers_and_gear_ratios_in_parallel(
+    lines: &[Line],
+    adjacency: Adjacency,
+    threads: usize,
+    band_rows: usize,
 ) -> (Vec<PartNumber>, Vec<GearRatio>) {
-    let maybe_first_line = lines.next();
-    match maybe_first_line {
-        Some(first_line) => {
-            let mut prev_line = None;
-            let mut current_line = first_line;
-
-            let mut part_numbers = vec![];
-            let mut gear_ratios = vec![];
-
-            loop {
-                let next_line = lines.next();
-                part_numbers.extend(find_part_numbers(
-                    prev_line.as_ref(),
-                    &current_line,
-                    next_line.as_ref(),
-                ));
-                gear_ratios.extend(find_gear_ratios(
-                    prev_line.as_ref(),
-                    &current_line,
-                    next_line.as_ref(),
-                ));
-                prev_line = Some(current_line);
+    let band_rows = band_rows.max(1);
 
-                match next_line {
-                    Some(line) => current_line = line,
-                    None => break,
-                }
-            }
+    let band_results = run_on_thread_pool(lines.len().div_ceil(band_rows), threads, |band| {
+        let start = band * band_rows;
+        let end = (start + band_rows).min(lines.len());
 
-            (part_numbers, gear_ratios)
+        let mut part_numbers = vec![];
+        let mut gear_ratios = vec![];
+        for y in start..end {
+            let nearby_lines = nearby_lines(lines, y, adjacency);
+            part_numbers.extend(find_part_numbers(&lines[y], &nearby_lines, adjacency));
+            gear_ratios.extend(find_gear_ratios(&lines[y], &nearby_lines, adjacency));
         }
-        None => (vec![], vec![]),
+        (part_numbers, gear_ratios)
+    });
+
+    let mut part_numbers = vec![];
+    let mut gear_ratios = vec![];
+    for (band_part_numbers, band_gear_ratios) in band_results {
+        part_numbers.extend(band_part_numbers);
+        gear_ratios.extend(band_gear_ratios);
     }
+    (part_numbers, gear_ratios)
 }
 
-const GEAR_SYMBOL: char = '*';
-const PERIOD: char = '.';
 const BASE_10: u32 = 10;
 
+/// Which characters are blank and which symbols are gears.
+/// Any other character that isn't a digit is a `SymbolType::Other` symbol.
+#[derive(PartialEq, Debug)]
+struct SymbolRules {
+    blanks: Vec<char>,
+    /// Gear character and how many adjacent numbers make it a gear
+    gears: Vec<(char, usize)>,
+}
+impl SymbolRules {
+    fn classify(&self, c: char) -> Option<SymbolType> {
+        if self.blanks.contains(&c) {
+            return None;
+        }
+        Some(
+            self.gears
+                .iter()
+                .find(|(gear, _)| *gear == c)
+                .map(|(_, required_numbers)| SymbolType::Gear(*required_numbers))
+                .unwrap_or(SymbolType::Other),
+        )
+    }
+}
+impl Default for SymbolRules {
+    /// `.` is blank and `*` is a gear when it touches exactly two numbers
+    fn default() -> Self {
+        Self {
+            blanks: vec!['.'],
+            gears: vec![('*', 2)],
+        }
+    }
+}
+impl FromStr for SymbolRules {
+    type Err = String;
+
+    /// Parses a table with one rule per line, either `blank <chars>` or
+    /// `gear <chars> <adjacent numbers>`. Blank lines and lines starting with
+    /// `#` are ignored. There must be a `blank` rule, since a table without one
+    /// would make every `.` a symbol.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = SymbolRules {
+            blanks: vec![],
+            gears: vec![],
+        };
+        for (line_num, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let columns: Vec<&str> = line.split_whitespace().collect();
+            let chars = columns.get(1).map(|chars| chars.chars()).ok_or_else(|| {
+                format!(
+                    "LINE {}: Expected characters after \"{}\"",
+                    line_num, columns[0]
+                )
+            })?;
+            for c in chars.clone() {
+                if c.is_ascii_digit() {
+                    return Err(format!(
+                        "LINE {}: Digits can't be symbols or blank",
+                        line_num
+                    ));
+                }
+                if rules.blanks.contains(&c) || rules.gears.iter().any(|(gear, _)| *gear == c) {
+                    return Err(format!(
+                        "LINE {}: \"{}\" has more than one rule",
+                        line_num, c
+                    ));
+                }
+            }
+
+            match columns.as_slice() {
+                ["blank", _] => rules.blanks.extend(chars),
+                ["gear", _, required_numbers] => {
+                    let required_numbers = required_numbers.parse::<usize>().map_err(|e| {
+                        format!("LINE {}: Failed to parse adjacent numbers: {}", line_num, e)
+                    })?;
+                    if required_numbers == 0 {
+                        return Err(format!(
+                            "LINE {}: A gear needs at least 1 adjacent number",
+                            line_num
+                        ));
+                    }
+                    rules
+                        .gears
+                        .extend(chars.map(|gear| (gear, required_numbers)));
+                }
+                _ => {
+                    return Err(format!(
+                        "LINE {}: Expected \"blank <chars>\" or \"gear <chars> <adjacent numbers>\", got \"{}\"",
+                        line_num, line
+                    ))
+                }
+            }
+        }
+        if rules.blanks.is_empty() {
+            return Err("Expected a \"blank <chars>\" rule, e.g. \"blank .\"".to_string());
+        }
+        Ok(rules)
+    }
+}
+
 struct NumberBuilder {
     y: usize,
     start_x: usize,
@@ -188,7 +508,7 @@ impl NumberBuilder {
     }
 }
 
-fn parse_line_into_symbols_and_numbers(line: &str, y: usize) -> Line {
+fn parse_line_into_symbols_and_numbers(line: &str, y: usize, rules: &SymbolRules) -> Line {
     let mut symbols = Vec::new();
     let mut numbers = Vec::new();
 
@@ -208,13 +528,8 @@ fn parse_line_into_symbols_and_numbers(line: &str, y: usize) -> Line {
                 numbers.push(number);
             }
 
-            if c != PERIOD {
-                let sym_type = if c == GEAR_SYMBOL {
-                    SymbolType::Gear
-                } else {
-                    SymbolType::Other
-                };
-                symbols.push(SymbolNode { sym_type, x, y });
+            if let Some(sym_type) = rules.classify(c) {
+                symbols.push(SymbolNode { sym_type, c, x, y });
             }
             None
         }
@@ -226,16 +541,1115 @@ fn parse_line_into_symbols_and_numbers(line: &str, y: usize) -> Line {
     Line { symbols, numbers }
 }
 
+mod render {
+    use std::str::FromStr;
+
+    use super::{gear_ratio, is_part_number, nearby_lines, Adjacency, Line};
+
+    /// How a cell of the schematic is highlighted
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub(crate) enum Highlight {
+        PartNumber,
+        OtherNumber,
+        /// A gear with exactly as many adjacent numbers as it requires
+        Gear,
+        OtherSymbol,
+    }
+    impl Highlight {
+        const ALL: [Highlight; 4] = [
+            Highlight::PartNumber,
+            Highlight::OtherNumber,
+            Highlight::Gear,
+            Highlight::OtherSymbol,
+        ];
+
+        fn ansi_code(&self) -> &'static str {
+            match self {
+                Highlight::PartNumber => "1;32",
+                Highlight::OtherNumber => "2;31",
+                Highlight::Gear => "1;33",
+                Highlight::OtherSymbol => "36",
+            }
+        }
+
+        fn css_class(&self) -> &'static str {
+            match self {
+                Highlight::PartNumber => "part",
+                Highlight::OtherNumber => "number",
+                Highlight::Gear => "gear",
+                Highlight::OtherSymbol => "symbol",
+            }
+        }
+
+        fn css_style(&self) -> &'static str {
+            match self {
+                Highlight::PartNumber => "fill: #4caf50; color: #4caf50; font-weight: bold;",
+                Highlight::OtherNumber => "fill: #e57373; color: #e57373;",
+                Highlight::Gear => "fill: #ffd54f; color: #ffd54f; font-weight: bold;",
+                Highlight::OtherSymbol => "fill: #4dd0e1; color: #4dd0e1;",
+            }
+        }
+
+        fn description(&self) -> &'static str {
+            match self {
+                Highlight::PartNumber => "part number",
+                Highlight::OtherNumber => "not a part number",
+                Highlight::Gear => "gear",
+                Highlight::OtherSymbol => "other symbol",
+            }
+        }
+    }
+
+    /// Columns `start_x..=end_x` of a line share a highlight
+    #[derive(PartialEq, Debug)]
+    pub(crate) struct Span {
+        pub(crate) start_x: usize,
+        pub(crate) end_x: usize,
+        pub(crate) highlight: Highlight,
+    }
+
+    /// Highlights every number and symbol in the current line, in column order
+    pub(crate) fn annotate_line(
+        current_line: &Line,
+        nearby_lines: &[&Line],
+        adjacency: Adjacency,
+    ) -> Vec<Span> {
+        let numbers = current_line.numbers.iter().map(|number| Span {
+            start_x: number.start_x,
+            end_x: number.end_x,
+            highlight: if is_part_number(number, nearby_lines, adjacency) {
+                Highlight::PartNumber
+            } else {
+                Highlight::OtherNumber
+            },
+        });
+        let symbols = current_line.symbols.iter().map(|symbol| Span {
+            start_x: symbol.x,
+            end_x: symbol.x,
+            highlight: match gear_ratio(symbol, nearby_lines, adjacency) {
+                Some(_) => Highlight::Gear,
+                None => Highlight::OtherSymbol,
+            },
+        });
+
+        let mut spans: Vec<Span> = numbers.chain(symbols).collect();
+        spans.sort_by_key(|span| span.start_x);
+        spans
+    }
+
+    /// Annotates every line with the lines within reach around it
+    pub(crate) fn annotate_lines(lines: &[Line], adjacency: Adjacency) -> Vec<Vec<Span>> {
+        (0..lines.len())
+            .map(|y| annotate_line(&lines[y], &nearby_lines(lines, y, adjacency), adjacency))
+            .collect()
+    }
+
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub(crate) enum RenderFormat {
+        Ansi,
+        Html,
+        Svg,
+    }
+    impl FromStr for RenderFormat {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "ansi" => Ok(RenderFormat::Ansi),
+                "html" => Ok(RenderFormat::Html),
+                "svg" => Ok(RenderFormat::Svg),
+                _ => Err(format!(
+                    "Unknown render format \"{}\", expected ansi, html or svg",
+                    s
+                )),
+            }
+        }
+    }
+
+    /// Splits a row of text into runs of characters that share a highlight
+    fn segments(text: &str, spans: &[Span]) -> Vec<(Option<Highlight>, String)> {
+        let mut segments: Vec<(Option<Highlight>, String)> = vec![];
+        let mut spans = spans.iter().peekable();
+        for (x, c) in text.chars().enumerate() {
+            while spans.next_if(|span| span.end_x < x).is_some() {}
+            let highlight = spans
+                .peek()
+                .filter(|span| span.start_x <= x)
+                .map(|span| span.highlight);
+
+            match segments.last_mut() {
+                Some((last_highlight, run)) if *last_highlight == highlight => run.push(c),
+                _ => segments.push((highlight, c.to_string())),
+            }
+        }
+        segments
+    }
+
+    fn escape_xml(s: &str) -> String {
+        s.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+
+    /// Reprints the schematic rows with their highlights
+    pub(crate) fn render(format: RenderFormat, rows: &[&str], annotations: &[Vec<Span>]) -> String {
+        match format {
+            RenderFormat::Ansi => render_ansi(rows, annotations),
+            RenderFormat::Html => render_html(rows, annotations),
+            RenderFormat::Svg => render_svg(rows, annotations),
+        }
+    }
+
+    fn render_ansi(rows: &[&str], annotations: &[Vec<Span>]) -> String {
+        let mut out = String::new();
+        for (text, spans) in rows.iter().zip(annotations) {
+            for (highlight, run) in segments(text, spans) {
+                match highlight {
+                    Some(highlight) => {
+                        out.push_str(&format!("\x1b[{}m{}\x1b[0m", highlight.ansi_code(), run))
+                    }
+                    None => out.push_str(&run),
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn css() -> String {
+        Highlight::ALL
+            .iter()
+            .map(|highlight| {
+                format!(
+                    ".{} {{ {} }}\n",
+                    highlight.css_class(),
+                    highlight.css_style()
+                )
+            })
+            .collect()
+    }
+
+    fn render_html(rows: &[&str], annotations: &[Vec<Span>]) -> String {
+        let mut out = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Schematic</title>\n<style>\n",
+        );
+        out.push_str("body { background: #1e1e1e; color: #808080; font-family: monospace; }\n");
+        out.push_str(&css());
+        out.push_str("</style>\n</head>\n<body>\n<p>\n");
+        for highlight in Highlight::ALL {
+            out.push_str(&format!(
+                "<span class=\"{}\">{}</span>\n",
+                highlight.css_class(),
+                highlight.description()
+            ));
+        }
+        out.push_str("</p>\n<pre>\n");
+        for (text, spans) in rows.iter().zip(annotations) {
+            for (highlight, run) in segments(text, spans) {
+                match highlight {
+                    Some(highlight) => out.push_str(&format!(
+                        "<span class=\"{}\">{}</span>",
+                        highlight.css_class(),
+                        escape_xml(&run)
+                    )),
+                    None => out.push_str(&escape_xml(&run)),
+                }
+            }
+            out.push('\n');
+        }
+        out.push_str("</pre>\n</body>\n</html>\n");
+        out
+    }
+
+    const SVG_FONT_SIZE: usize = 14;
+    /// Monospace glyphs are about 0.6em wide
+    const SVG_CHAR_WIDTH: f64 = SVG_FONT_SIZE as f64 * 0.6;
+    const SVG_LINE_HEIGHT: usize = SVG_FONT_SIZE + 4;
+
+    fn render_svg(rows: &[&str], annotations: &[Vec<Span>]) -> String {
+        let columns = rows
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0);
+        let width = (columns as f64 * SVG_CHAR_WIDTH).ceil() as usize;
+        let height = rows.len() * SVG_LINE_HEIGHT;
+
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+            w = width,
+            h = height
+        );
+        out.push_str("<style>\n");
+        out.push_str(&format!(
+            "text {{ font-family: monospace; font-size: {}px; fill: #808080; white-space: pre; }}\n",
+            SVG_FONT_SIZE
+        ));
+        out.push_str(&css());
+        out.push_str("</style>\n<rect width=\"100%\" height=\"100%\" fill=\"#1e1e1e\"/>\n");
+        for (y, (text, spans)) in rows.iter().zip(annotations).enumerate() {
+            out.push_str(&format!(
+                "<text x=\"0\" y=\"{}\" xml:space=\"preserve\">",
+                (y + 1) * SVG_LINE_HEIGHT - 4
+            ));
+            for (highlight, run) in segments(text, spans) {
+                match highlight {
+                    Some(highlight) => out.push_str(&format!(
+                        "<tspan class=\"{}\">{}</tspan>",
+                        highlight.css_class(),
+                        escape_xml(&run)
+                    )),
+                    None => out.push_str(&escape_xml(&run)),
+                }
+            }
+            out.push_str("</text>\n");
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::{annotate_lines, render, Highlight, RenderFormat, Span};
+        use crate::{
+            test::{parse_schematic, SCHEMATIC},
+            Adjacency, SymbolRules,
+        };
+
+        fn annotate(schematic: &str) -> Vec<Vec<Span>> {
+            annotate_lines(
+                &parse_schematic(schematic, &SymbolRules::default()),
+                Adjacency::default(),
+            )
+        }
+
+        #[test]
+        fn test_annotates_numbers_and_symbols() {
+            let annotations = annotate(SCHEMATIC);
+
+            let highlights: Vec<Vec<(usize, Highlight)>> = annotations
+                .iter()
+                .map(|spans| {
+                    spans
+                        .iter()
+                        .map(|span| (span.start_x, span.highlight))
+                        .collect()
+                })
+                .collect();
+            assert_eq!(
+                highlights,
+                vec![
+                    vec![(0, Highlight::PartNumber), (5, Highlight::OtherNumber)],
+                    vec![(3, Highlight::Gear)],
+                    vec![(2, Highlight::PartNumber), (6, Highlight::PartNumber)],
+                    vec![(6, Highlight::OtherSymbol)],
+                    vec![(0, Highlight::PartNumber), (3, Highlight::OtherSymbol)],
+                ]
+            );
+        }
+
+        #[test]
+        fn test_renders_ansi() {
+            let rows: Vec<&str> = SCHEMATIC.lines().collect();
+            let rendered = render(RenderFormat::Ansi, &rows[..2], &annotate(SCHEMATIC)[..2]);
+            assert_eq!(
+                rendered,
+                "\x1b[1;32m467\x1b[0m..\x1b[2;31m114\x1b[0m..\n...\x1b[1;33m*\x1b[0m......\n"
+            );
+        }
+
+        #[test]
+        fn test_renders_escaped_html_and_svg() {
+            let schematic = "1<..\n.&.2";
+            let rows: Vec<&str> = schematic.lines().collect();
+            let annotations = annotate(schematic);
+
+            let html = render(RenderFormat::Html, &rows, &annotations);
+            assert!(html.starts_with("<!DOCTYPE html>"));
+            assert!(html
+                .contains("<span class=\"part\">1</span><span class=\"symbol\">&lt;</span>..\n"));
+            assert!(html.contains(
+                ".<span class=\"symbol\">&amp;</span>.<span class=\"number\">2</span>\n"
+            ));
+
+            let svg = render(RenderFormat::Svg, &rows, &annotations);
+            assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+            assert!(svg.contains("<tspan class=\"symbol\">&amp;</tspan>"));
+            assert!(svg.trim_end().ends_with("</svg>"));
+
+            assert_eq!("svg".parse(), Ok(RenderFormat::Svg));
+            assert!("png".parse::<RenderFormat>().is_err());
+        }
+    }
+}
+
+mod graph {
+    use std::str::FromStr;
+
+    use super::{
+        gear_ratio, nearby_line_indices, nearby_lines, Adjacency, Line, NumberNode, SymbolNode,
+        SymbolType,
+    };
+
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub(crate) enum GraphFormat {
+        Dot,
+        Json,
+    }
+    impl FromStr for GraphFormat {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "dot" => Ok(GraphFormat::Dot),
+                "json" => Ok(GraphFormat::Json),
+                _ => Err(format!(
+                    "Unknown graph format \"{}\", expected dot or json",
+                    s
+                )),
+            }
+        }
+    }
+
+    pub(crate) struct GraphSymbol<'a> {
+        node: &'a SymbolNode,
+        /// Set when the symbol is a gear with exactly as many numbers as it requires
+        gear_ratio: Option<u64>,
+    }
+
+    /// The bipartite graph of numbers and symbols, with an edge wherever
+    /// `NumberNode::is_adjacent_to` holds. Nodes are numbered in reading order.
+    pub(crate) struct AdjacencyGraph<'a> {
+        numbers: Vec<&'a NumberNode>,
+        symbols: Vec<GraphSymbol<'a>>,
+        /// Pairs of indexes into `numbers` and `symbols`
+        edges: Vec<(usize, usize)>,
+    }
+    impl<'a> AdjacencyGraph<'a> {
+        pub(crate) fn new(lines: &'a [Line], adjacency: Adjacency) -> Self {
+            let column_reach = adjacency.column_reach();
+
+            // Index of the first symbol on each line
+            let mut first_symbol = Vec::with_capacity(lines.len());
+            let mut symbols = vec![];
+            for (y, line) in lines.iter().enumerate() {
+                first_symbol.push(symbols.len());
+                let nearby_lines = nearby_lines(lines, y, adjacency);
+                symbols.extend(line.symbols.iter().map(|node| GraphSymbol {
+                    node,
+                    gear_ratio: gear_ratio(node, &nearby_lines, adjacency).map(|ratio| ratio.0),
+                }));
+            }
+
+            let mut numbers = vec![];
+            let mut edges = vec![];
+            for (y, line) in lines.iter().enumerate() {
+                for number in line.numbers.iter() {
+                    let number_idx = numbers.len();
+                    numbers.push(number);
+
+                    let min_x = number.start_x.saturating_sub(column_reach);
+                    let max_x = number.end_x + column_reach;
+                    for near_y in nearby_line_indices(y, lines.len(), adjacency) {
+                        let near_line = &lines[near_y];
+                        let first_in_columns =
+                            near_line.symbols.partition_point(|symbol| symbol.x < min_x);
+                        for (offset, symbol) in near_line
+                            .symbols_in_columns(min_x, max_x)
+                            .iter()
+                            .enumerate()
+                        {
+                            if number.is_adjacent_to(symbol, adjacency) {
+                                edges.push((
+                                    number_idx,
+                                    first_symbol[near_y] + first_in_columns + offset,
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+
+            AdjacencyGraph {
+                numbers,
+                symbols,
+                edges,
+            }
+        }
+
+        fn number_degrees(&self) -> Vec<usize> {
+            let mut degrees = vec![0; self.numbers.len()];
+            for (number_idx, _) in self.edges.iter() {
+                degrees[*number_idx] += 1;
+            }
+            degrees
+        }
+
+        fn symbol_degrees(&self) -> Vec<usize> {
+            let mut degrees = vec![0; self.symbols.len()];
+            for (_, symbol_idx) in self.edges.iter() {
+                degrees[*symbol_idx] += 1;
+            }
+            degrees
+        }
+
+        pub(crate) fn format(&self, format: GraphFormat) -> String {
+            match format {
+                GraphFormat::Dot => self.format_dot(),
+                GraphFormat::Json => self.format_json(),
+            }
+        }
+
+        /// Numbers are boxes and symbols are circles, pinned to their place in the
+        /// schematic for `neato -n`
+        fn format_dot(&self) -> String {
+            let mut out = String::from("graph schematic {\n    node [fontname=\"monospace\"];\n");
+            for (idx, (number, degree)) in
+                self.numbers.iter().zip(self.number_degrees()).enumerate()
+            {
+                out.push_str(&format!(
+                    "    n{} [label=\"{}\", shape=box, value={}, start_x={}, end_x={}, y={}, degree={}, pos=\"{},{}!\"{}];\n",
+                    idx,
+                    number.value,
+                    number.value,
+                    number.start_x,
+                    number.end_x,
+                    number.y,
+                    degree,
+                    number.start_x * 72,
+                    -(number.y as i64) * 72,
+                    if degree > 0 { ", style=bold" } else { "" },
+                ));
+            }
+            for (idx, (symbol, degree)) in
+                self.symbols.iter().zip(self.symbol_degrees()).enumerate()
+            {
+                out.push_str(&format!(
+                    "    s{} [label=\"{}\", shape=circle, type=\"{}\", x={}, y={}, degree={}, pos=\"{},{}!\"{}];\n",
+                    idx,
+                    escape_dot(symbol.node.c),
+                    symbol_type_name(symbol),
+                    symbol.node.x,
+                    symbol.node.y,
+                    degree,
+                    symbol.node.x * 72,
+                    -(symbol.node.y as i64) * 72,
+                    match symbol.gear_ratio {
+                        Some(ratio) => format!(", gear_ratio={}, style=bold", ratio),
+                        None => String::new(),
+                    },
+                ));
+            }
+            for (number_idx, symbol_idx) in self.edges.iter() {
+                out.push_str(&format!("    n{} -- s{};\n", number_idx, symbol_idx));
+            }
+            out.push_str("}\n");
+            out
+        }
+
+        fn format_json(&self) -> String {
+            let numbers: Vec<String> = self
+                .numbers
+                .iter()
+                .zip(self.number_degrees())
+                .enumerate()
+                .map(|(idx, (number, degree))| {
+                    format!(
+                        "{{\"id\":\"n{}\",\"value\":{},\"start_x\":{},\"end_x\":{},\"y\":{},\"degree\":{}}}",
+                        idx, number.value, number.start_x, number.end_x, number.y, degree
+                    )
+                })
+                .collect();
+            let symbols: Vec<String> = self
+                .symbols
+                .iter()
+                .zip(self.symbol_degrees())
+                .enumerate()
+                .map(|(idx, (symbol, degree))| {
+                    format!(
+                        "{{\"id\":\"s{}\",\"char\":{},\"type\":\"{}\",\"x\":{},\"y\":{},\"degree\":{},\"gear_ratio\":{}}}",
+                        idx,
+                        json_char(symbol.node.c),
+                        symbol_type_name(symbol),
+                        symbol.node.x,
+                        symbol.node.y,
+                        degree,
+                        symbol
+                            .gear_ratio
+                            .map_or("null".to_string(), |ratio| ratio.to_string())
+                    )
+                })
+                .collect();
+            let edges: Vec<String> = self
+                .edges
+                .iter()
+                .map(|(number_idx, symbol_idx)| {
+                    format!(
+                        "{{\"number\":\"n{}\",\"symbol\":\"s{}\"}}",
+                        number_idx, symbol_idx
+                    )
+                })
+                .collect();
+
+            format!(
+                "{{\"numbers\":[{}],\"symbols\":[{}],\"edges\":[{}]}}\n",
+                numbers.join(","),
+                symbols.join(","),
+                edges.join(",")
+            )
+        }
+    }
+
+    fn symbol_type_name(symbol: &GraphSymbol) -> &'static str {
+        match symbol.node.sym_type {
+            SymbolType::Gear(_) => "gear",
+            SymbolType::Other => "other",
+        }
+    }
+
+    fn escape_dot(c: char) -> String {
+        match c {
+            '"' | '\\' => format!("\\{}", c),
+            _ => c.to_string(),
+        }
+    }
+
+    fn json_char(c: char) -> String {
+        match c {
+            '"' | '\\' => format!("\"\\{}\"", c),
+            c if c.is_control() => format!("\"\\u{:04x}\"", c as u32),
+            _ => format!("\"{}\"", c),
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::{AdjacencyGraph, GraphFormat};
+        use crate::{
+            test::{parse_schematic, SCHEMATIC},
+            Adjacency, SymbolRules,
+        };
+
+        #[test]
+        fn test_builds_bipartite_graph() {
+            let lines = parse_schematic(SCHEMATIC, &SymbolRules::default());
+            let graph = AdjacencyGraph::new(&lines, Adjacency::default());
+
+            assert_eq!(
+                graph
+                    .numbers
+                    .iter()
+                    .map(|number| number.value)
+                    .collect::<Vec<_>>(),
+                vec![467, 114, 35, 633, 617]
+            );
+            assert_eq!(
+                graph
+                    .symbols
+                    .iter()
+                    .map(|symbol| (symbol.node.c, symbol.gear_ratio))
+                    .collect::<Vec<_>>(),
+                vec![('*', Some(16345)), ('#', None), ('*', None)]
+            );
+            assert_eq!(graph.edges, vec![(0, 0), (2, 0), (3, 1), (4, 2)]);
+            assert_eq!(graph.number_degrees(), vec![1, 0, 1, 1, 1]);
+            assert_eq!(graph.symbol_degrees(), vec![2, 1, 1]);
+        }
+
+        #[test]
+        fn test_formats_dot_and_json() {
+            let schematic = "1\"2\n.*.";
+            let lines = parse_schematic(schematic, &SymbolRules::default());
+            let graph = AdjacencyGraph::new(&lines, Adjacency::default());
+
+            assert_eq!(
+                graph.format(GraphFormat::Dot),
+                "\
+graph schematic {
+    node [fontname=\"monospace\"];
+    n0 [label=\"1\", shape=box, value=1, start_x=0, end_x=0, y=0, degree=2, pos=\"0,0!\", style=bold];
+    n1 [label=\"2\", shape=box, value=2, start_x=2, end_x=2, y=0, degree=2, pos=\"144,0!\", style=bold];
+    s0 [label=\"\\\"\", shape=circle, type=\"other\", x=1, y=0, degree=2, pos=\"72,0!\"];
+    s1 [label=\"*\", shape=circle, type=\"gear\", x=1, y=1, degree=2, pos=\"72,-72!\", gear_ratio=2, style=bold];
+    n0 -- s0;
+    n0 -- s1;
+    n1 -- s0;
+    n1 -- s1;
+}
+"
+            );
+            assert_eq!(
+                graph.format(GraphFormat::Json),
+                concat!(
+                    "{\"numbers\":[",
+                    "{\"id\":\"n0\",\"value\":1,\"start_x\":0,\"end_x\":0,\"y\":0,\"degree\":2},",
+                    "{\"id\":\"n1\",\"value\":2,\"start_x\":2,\"end_x\":2,\"y\":0,\"degree\":2}",
+                    "],\"symbols\":[",
+                    "{\"id\":\"s0\",\"char\":\"\\\"\",\"type\":\"other\",\"x\":1,\"y\":0,\"degree\":2,\"gear_ratio\":null},",
+                    "{\"id\":\"s1\",\"char\":\"*\",\"type\":\"gear\",\"x\":1,\"y\":1,\"degree\":2,\"gear_ratio\":2}",
+                    "],\"edges\":[",
+                    "{\"number\":\"n0\",\"symbol\":\"s0\"},{\"number\":\"n0\",\"symbol\":\"s1\"},",
+                    "{\"number\":\"n1\",\"symbol\":\"s0\"},{\"number\":\"n1\",\"symbol\":\"s1\"}",
+                    "]}\n"
+                )
+            );
+
+            assert_eq!("dot".parse(), Ok(GraphFormat::Dot));
+            assert!("csv".parse::<GraphFormat>().is_err());
+        }
+    }
+}
+
+mod schematic {
+    use super::{
+        gear_ratio, nearby_lines, Adjacency, GearRatio, Line, NumberNode, SymbolNode, SymbolType,
+    };
+
+    /// What occupies a cell of the schematic
+    #[derive(PartialEq, Debug)]
+    pub(crate) enum Cell<'a> {
+        Number(&'a NumberNode),
+        Symbol(&'a SymbolNode),
+    }
+
+    /// Every parsed line of a schematic, for answering questions about single cells.
+    /// `x` is the column and `y` the row, both counted from 0.
+    pub(crate) struct Schematic {
+        lines: Vec<Line>,
+        adjacency: Adjacency,
+    }
+    impl Schematic {
+        pub(crate) fn new(lines: Vec<Line>, adjacency: Adjacency) -> Self {
+            Schematic { lines, adjacency }
+        }
+
+        fn nearby_lines(&self, y: usize) -> Vec<&Line> {
+            nearby_lines(&self.lines, y, self.adjacency)
+        }
+
+        /// The number or symbol at a cell, or `None` when it is blank or outside
+        /// the schematic
+        pub(crate) fn at(&self, x: usize, y: usize) -> Option<Cell<'_>> {
+            let line = self.lines.get(y)?;
+            if let Some(number) = line.numbers_in_columns(x, x).first() {
+                return Some(Cell::Number(number));
+            }
+            line.symbols_in_columns(x, x).first().map(Cell::Symbol)
+        }
+
+        /// Numbers touching the symbol, in reading order
+        pub(crate) fn numbers_adjacent_to(&self, symbol: &SymbolNode) -> Vec<&NumberNode> {
+            let reach = self.adjacency.column_reach();
+            self.nearby_lines(symbol.y)
+                .into_iter()
+                .flat_map(|line| {
+                    line.numbers_in_columns(symbol.x.saturating_sub(reach), symbol.x + reach)
+                })
+                .filter(|number| number.is_adjacent_to(symbol, self.adjacency))
+                .collect()
+        }
+
+        /// Symbols touching the number, in reading order. The number is a part
+        /// number exactly when there are any.
+        pub(crate) fn symbols_adjacent_to(&self, number: &NumberNode) -> Vec<&SymbolNode> {
+            let reach = self.adjacency.column_reach();
+            self.nearby_lines(number.y)
+                .into_iter()
+                .flat_map(|line| {
+                    line.symbols_in_columns(
+                        number.start_x.saturating_sub(reach),
+                        number.end_x + reach,
+                    )
+                })
+                .filter(|symbol| number.is_adjacent_to(symbol, self.adjacency))
+                .collect()
+        }
+
+        pub(crate) fn gear_ratio(&self, symbol: &SymbolNode) -> Option<GearRatio> {
+            gear_ratio(symbol, &self.nearby_lines(symbol.y), self.adjacency)
+        }
+    }
+
+    fn describe_symbol(symbol: &SymbolNode) -> String {
+        format!("symbol {} at ({}, {})", symbol.c, symbol.x, symbol.y)
+    }
+
+    fn describe_number(number: &NumberNode) -> String {
+        if number.start_x == number.end_x {
+            format!(
+                "number {} at ({}, {})",
+                number.value, number.start_x, number.y
+            )
+        } else {
+            format!(
+                "number {} at ({}..={}, {})",
+                number.value, number.start_x, number.end_x, number.y
+            )
+        }
+    }
+
+    pub(crate) const QUERY_HELP: &str = "\
+Commands, with x the column and y the row counted from 0:
+  at <x> <y>        what is at a cell
+  adjacent <x> <y>  which numbers touch the symbol at a cell
+  part <x> <y>      whether the number at a cell is a part number and why
+  help              show this help
+  quit              stop";
+
+    /// Answers one line typed at the query prompt
+    pub(crate) fn answer_query(schematic: &Schematic, query: &str) -> Result<String, String> {
+        let words: Vec<&str> = query.split_whitespace().collect();
+        let (command, coords) = match words.split_first() {
+            Some((command, coords)) => (*command, coords),
+            None => return Ok(String::new()),
+        };
+        match command {
+            "help" => return Ok(QUERY_HELP.to_string()),
+            "at" | "adjacent" | "part" => {}
+            _ => return Err(format!("Unknown command \"{}\", try \"help\"", command)),
+        }
+
+        let (x, y) = match coords {
+            [x, y] => (
+                x.parse::<usize>()
+                    .map_err(|_| format!("Invalid column \"{}\"", x))?,
+                y.parse::<usize>()
+                    .map_err(|_| format!("Invalid row \"{}\"", y))?,
+            ),
+            _ => return Err(format!("Expected \"{} <x> <y>\"", command)),
+        };
+        let cell = schematic.at(x, y);
+
+        match command {
+            "at" => Ok(match cell {
+                Some(Cell::Number(number)) => describe_number(number),
+                Some(Cell::Symbol(symbol)) => match symbol.sym_type {
+                    SymbolType::Gear(required_numbers) => format!(
+                        "{}, a gear when {} numbers touch it",
+                        describe_symbol(symbol),
+                        required_numbers
+                    ),
+                    SymbolType::Other => describe_symbol(symbol),
+                },
+                None => format!("nothing at ({}, {})", x, y),
+            }),
+            "adjacent" => {
+                let Some(Cell::Symbol(symbol)) = cell else {
+                    return Err(format!("No symbol at ({}, {})", x, y));
+                };
+                let numbers = schematic.numbers_adjacent_to(symbol);
+                let mut answer = format!(
+                    "{} touches {} number{}",
+                    describe_symbol(symbol),
+                    numbers.len(),
+                    if numbers.len() == 1 { "" } else { "s" }
+                );
+                for number in numbers.iter() {
+                    answer.push_str(&format!("\n  {}", describe_number(number)));
+                }
+                match (symbol.sym_type, schematic.gear_ratio(symbol)) {
+                    (_, Some(ratio)) => answer.push_str(&format!("\ngear ratio {}", ratio.0)),
+                    (SymbolType::Gear(required_numbers), None) => answer.push_str(&format!(
+                        "\nnot a gear, it needs exactly {} numbers",
+                        required_numbers
+                    )),
+                    (SymbolType::Other, None) => {}
+                }
+                Ok(answer)
+            }
+            "part" => {
+                let Some(Cell::Number(number)) = cell else {
+                    return Err(format!("No number at ({}, {})", x, y));
+                };
+                let symbols = schematic.symbols_adjacent_to(number);
+                if symbols.is_empty() {
+                    return Ok(format!(
+                        "{} is not a part number, no symbol touches it",
+                        describe_number(number)
+                    ));
+                }
+                let mut answer =
+                    format!("{} is a part number, it touches", describe_number(number));
+                for symbol in symbols {
+                    answer.push_str(&format!("\n  {}", describe_symbol(symbol)));
+                }
+                Ok(answer)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::{answer_query, Cell, Schematic};
+        use crate::{
+            test::{parse_schematic, SCHEMATIC},
+            Adjacency, SymbolRules,
+        };
+
+        #[test]
+        fn test_answers_point_queries() {
+            let schematic = Schematic::new(
+                parse_schematic(SCHEMATIC, &SymbolRules::default()),
+                Adjacency::default(),
+            );
+
+            let Some(Cell::Number(number)) = schematic.at(1, 0) else {
+                panic!("Expected 467 at (1, 0)");
+            };
+            assert_eq!(number.value, 467);
+            assert_eq!(schematic.at(3, 0), None);
+            assert_eq!(schematic.at(20, 0), None);
+            assert_eq!(schematic.at(0, 20), None);
+
+            let Some(Cell::Symbol(gear)) = schematic.at(3, 1) else {
+                panic!("Expected a gear at (3, 1)");
+            };
+            assert_eq!(
+                schematic
+                    .numbers_adjacent_to(gear)
+                    .iter()
+                    .map(|number| number.value)
+                    .collect::<Vec<_>>(),
+                vec![467, 35]
+            );
+
+            let Some(Cell::Number(number)) = schematic.at(7, 0) else {
+                panic!("Expected 114 at (7, 0)");
+            };
+            assert!(schematic.symbols_adjacent_to(number).is_empty());
+
+            let orthogonal = Schematic::new(
+                parse_schematic(SCHEMATIC, &SymbolRules::default()),
+                Adjacency::Orthogonal,
+            );
+            let Some(Cell::Symbol(gear)) = orthogonal.at(3, 1) else {
+                panic!("Expected a gear at (3, 1)");
+            };
+            assert_eq!(
+                orthogonal
+                    .numbers_adjacent_to(gear)
+                    .iter()
+                    .map(|number| number.value)
+                    .collect::<Vec<_>>(),
+                vec![35]
+            );
+        }
+
+        #[test]
+        fn test_answers_prompt_commands() {
+            let schematic = Schematic::new(
+                parse_schematic(SCHEMATIC, &SymbolRules::default()),
+                Adjacency::default(),
+            );
+            let ask = |query: &str| answer_query(&schematic, query);
+
+            assert_eq!(ask("at 6 2"), Ok("number 633 at (6..=8, 2)".to_string()));
+            assert_eq!(
+                ask("at 3 4"),
+                Ok("symbol * at (3, 4), a gear when 2 numbers touch it".to_string())
+            );
+            assert_eq!(ask("at 0 1"), Ok("nothing at (0, 1)".to_string()));
+            assert_eq!(
+                ask("adjacent 3 1"),
+                Ok("symbol * at (3, 1) touches 2 numbers\n  number 467 at (0..=2, 0)\n  number 35 at (2..=3, 2)\ngear ratio 16345".to_string())
+            );
+            assert_eq!(
+                ask("adjacent 3 4"),
+                Ok("symbol * at (3, 4) touches 1 number\n  number 617 at (0..=2, 4)\nnot a gear, it needs exactly 2 numbers".to_string())
+            );
+            assert_eq!(
+                ask("part 8 2"),
+                Ok(
+                    "number 633 at (6..=8, 2) is a part number, it touches\n  symbol # at (6, 3)"
+                        .to_string()
+                )
+            );
+            assert_eq!(
+                ask("part 5 0"),
+                Ok(
+                    "number 114 at (5..=7, 0) is not a part number, no symbol touches it"
+                        .to_string()
+                )
+            );
+            assert!(ask("part 3 1").is_err());
+            assert!(ask("adjacent 0 0").is_err());
+            assert!(ask("at 1").is_err());
+            assert!(ask("at x 1").is_err());
+            assert_eq!(
+                ask("where"),
+                Err("Unknown command \"where\", try \"help\"".to_string())
+            );
+            assert_eq!(ask(""), Ok(String::new()));
+        }
+    }
+}
+
+struct Args {
+    adjacency: Adjacency,
+    symbol_rules: SymbolRules,
+    render_format: Option<RenderFormat>,
+    graph_format: Option<GraphFormat>,
+    /// Load the schematic from this file and answer queries typed on stdin
+    query_path: Option<String>,
+    /// Process the schematic in bands on this many threads. Only applies to the
+    /// part number and gear ratio totals
+    threads: Option<usize>,
+}
+
+/// Usage: `day-03 [--adjacency <4|8|radius:N>] [--symbols <FILE>] [--render <ansi|html|svg>]
+/// [--graph <dot|json>] [--threads <N>] [--query <FILE>]`
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut adjacency = Adjacency::default();
+    let mut symbol_rules = SymbolRules::default();
+    let mut render_format = None;
+    let mut graph_format = None;
+    let mut query_path = None;
+    let mut threads = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--adjacency" => {
+                adjacency = args
+                    .next()
+                    .ok_or("Expected an adjacency after --adjacency")?
+                    .parse()?
+            }
+            "--symbols" => {
+                let path = args.next().ok_or("Expected a file path after --symbols")?;
+                symbol_rules = std::fs::read_to_string(&path)
+                    .map_err(|e| format!("Failed to read symbols file {}: {}", path, e))?
+                    .parse()
+                    .map_err(|e| format!("Failed to parse symbols file {}: {}", path, e))?;
+            }
+            "--render" => {
+                render_format = Some(
+                    args.next()
+                        .ok_or("Expected a format after --render")?
+                        .parse()?,
+                )
+            }
+            "--graph" => {
+                graph_format = Some(
+                    args.next()
+                        .ok_or("Expected a format after --graph")?
+                        .parse()?,
+                )
+            }
+            "--query" => {
+                query_path = Some(args.next().ok_or("Expected a file path after --query")?)
+            }
+            "--threads" => {
+                let value = args
+                    .next()
+                    .ok_or("Expected a thread count after --threads")?;
+                threads = match value.parse::<usize>() {
+                    Ok(count) if count > 0 => Some(count),
+                    _ => return Err(format!("Invalid thread count \"{}\"", value)),
+                }
+            }
+            _ => return Err(format!("Unknown argument \"{}\"", arg)),
+        }
+    }
+    if render_format.is_some() && graph_format.is_some() {
+        return Err("Only one of --render and --graph can be given".to_string());
+    }
+    if threads.is_some()
+        && (render_format.is_some() || graph_format.is_some() || query_path.is_some())
+    {
+        return Err("--threads can't be combined with --render, --graph or --query".to_string());
+    }
+    Ok(Args {
+        adjacency,
+        symbol_rules,
+        render_format,
+        graph_format,
+        query_path,
+        threads,
+    })
+}
+
 fn main() {
-    let lines = std::io::stdin()
-        .lines()
-        .enumerate()
-        .map(|(y, line)| parse_line_into_symbols_and_numbers(&line.unwrap(), y));
+    let args = parse_args(std::env::args().skip(1)).unwrap_or_else(|e| panic!("{}", e));
+
+    if let Some(path) = args.query_path {
+        let text = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("Failed to read schematic {}: {}", path, e));
+        let rows: Vec<&str> = text.lines().collect();
+        let schematic = Schematic::new(
+            rows.iter()
+                .enumerate()
+                .map(|(y, row)| parse_line_into_symbols_and_numbers(row, y, &args.symbol_rules))
+                .collect(),
+            args.adjacency,
+        );
 
-    let (part_numbers, gear_ratios) = find_all_part_numbers_and_gear_ratios(lines);
+        println!("{}", QUERY_HELP);
+        let mut stdin = std::io::stdin().lines();
+        loop {
+            print!("> ");
+            std::io::stdout().flush().unwrap();
+            let Some(query) = stdin.next() else {
+                break;
+            };
+            let query = query.unwrap();
+            if matches!(query.trim(), "quit" | "exit") {
+                break;
+            }
+            match answer_query(&schematic, &query) {
+                Ok(answer) if answer.is_empty() => {}
+                Ok(answer) => println!("{}", answer),
+                Err(e) => println!("error: {}", e),
+            }
+        }
+        return;
+    }
+
+    if args.render_format.is_some() || args.graph_format.is_some() {
+        let rows: Vec<String> = std::io::stdin().lines().map(|line| line.unwrap()).collect();
+        let lines: Vec<Line> = rows
+            .iter()
+            .enumerate()
+            .map(|(y, row)| parse_line_into_symbols_and_numbers(row, y, &args.symbol_rules))
+            .collect();
+        let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+        if let Some(format) = args.render_format {
+            print!(
+                "{}",
+                render(format, &rows, &annotate_lines(&lines, args.adjacency))
+            );
+        }
+        if let Some(format) = args.graph_format {
+            print!(
+                "{}",
+                AdjacencyGraph::new(&lines, args.adjacency).format(format)
+            );
+        }
+        return;
+    }
+
+    let (part_numbers, gear_ratios) = match args.threads {
+        Some(threads) => {
+            let rows: Vec<String> = std::io::stdin().lines().map(|line| line.unwrap()).collect();
+            let lines =
+                parse_lines_in_parallel(&rows, &args.symbol_rules, threads, DEFAULT_BAND_ROWS);
+            find_all_part_numbers_and_gear_ratios_in_parallel(
+                &lines,
+                args.adjacency,
+                threads,
+                DEFAULT_BAND_ROWS,
+            )
+        }
+        None => {
+            let lines = std::io::stdin().lines().enumerate().map(|(y, line)| {
+                parse_line_into_symbols_and_numbers(&line.unwrap(), y, &args.symbol_rules)
+            });
+            find_all_part_numbers_and_gear_ratios(lines, args.adjacency)
+        }
+    };
 
     let part_numbers_sum: u32 = part_numbers.iter().map(|p| p.0).sum();
-    let gear_ratios_sum: u32 = gear_ratios.iter().map(|g| g.0).sum();
+    let gear_ratios_sum: u64 = gear_ratios.iter().map(|g| g.0).sum();
 
     println!("Sum of part numbers: {}", part_numbers_sum);
     println!("Sum of gear ratios: {}", gear_ratios_sum);
@@ -243,11 +1657,13 @@ fn main() {
 
 #[cfg(test)]
 mod test {
-    use std::collections::HashSet;
+    use std::{collections::HashSet, time::Instant};
 
     use crate::{
-        find_all_part_numbers_and_gear_ratios, parse_line_into_symbols_and_numbers, GearRatio,
-        Line, NumberNode, PartNumber, SymbolNode, SymbolType,
+        find_all_part_numbers_and_gear_ratios, find_all_part_numbers_and_gear_ratios_in_parallel,
+        find_gear_ratios, find_part_numbers, nearby_lines, parse_line_into_symbols_and_numbers,
+        parse_lines_in_parallel, Adjacency, GearRatio, Line, NumberNode, PartNumber, SymbolNode,
+        SymbolRules, SymbolType,
     };
 
     fn get_expected_part_numbers() -> Vec<PartNumber> {
@@ -287,7 +1703,8 @@ mod test {
                 "...*......",
                 Line {
                     symbols: vec![SymbolNode {
-                        sym_type: crate::SymbolType::Gear,
+                        sym_type: crate::SymbolType::Gear(2),
+                        c: '*',
                         x: 3,
                         y: 0,
                     }],
@@ -319,6 +1736,7 @@ mod test {
                 Line {
                     symbols: vec![SymbolNode {
                         sym_type: SymbolType::Other,
+                        c: '#',
                         x: 6,
                         y: 0,
                     }],
@@ -329,7 +1747,8 @@ mod test {
                 "617*......",
                 Line {
                     symbols: vec![SymbolNode {
-                        sym_type: SymbolType::Gear,
+                        sym_type: SymbolType::Gear(2),
+                        c: '*',
                         x: 3,
                         y: 0,
                     }],
@@ -346,6 +1765,7 @@ mod test {
                 Line {
                     symbols: vec![SymbolNode {
                         sym_type: SymbolType::Other,
+                        c: '+',
                         x: 5,
                         y: 0,
                     }],
@@ -387,11 +1807,13 @@ mod test {
                     symbols: vec![
                         SymbolNode {
                             sym_type: SymbolType::Other,
+                            c: '$',
                             x: 3,
                             y: 0,
                         },
                         SymbolNode {
-                            sym_type: SymbolType::Gear,
+                            sym_type: SymbolType::Gear(2),
+                            c: '*',
                             x: 5,
                             y: 0,
                         },
@@ -440,7 +1862,8 @@ mod test {
                 "123*456",
                 Line {
                     symbols: vec![SymbolNode {
-                        sym_type: SymbolType::Gear,
+                        sym_type: SymbolType::Gear(2),
+                        c: '*',
                         x: 3,
                         y: 0,
                     }],
@@ -465,12 +1888,11 @@ mod test {
 
     #[test]
     fn test_parses_lines() {
-        let test_cases = get_test_cases()
-            .into_iter()
-            .chain(get_parser_edge_cases().into_iter());
+        let test_cases = get_test_cases().into_iter().chain(get_parser_edge_cases());
 
         for (line_str, expected_line) in test_cases {
-            let actual_line = parse_line_into_symbols_and_numbers(line_str, 0);
+            let actual_line =
+                parse_line_into_symbols_and_numbers(line_str, 0, &SymbolRules::default());
             assert_eq!(
                 actual_line, expected_line,
                 "Failed parsing LINE '{}'!",
@@ -483,6 +1905,7 @@ mod test {
     fn test_finds_right_part_numbers() {
         let (part_numbers, _) = find_all_part_numbers_and_gear_ratios(
             get_test_cases().into_iter().map(|(_, line)| line),
+            Adjacency::default(),
         );
 
         let expected_part_numbers = get_expected_part_numbers();
@@ -498,6 +1921,7 @@ mod test {
     fn test_sums_part_numbers() {
         let (part_numbers, _) = find_all_part_numbers_and_gear_ratios(
             get_test_cases().into_iter().map(|(_, line)| line),
+            Adjacency::default(),
         );
 
         let part_numbers_sum: u32 = part_numbers.iter().map(|p| p.0).sum();
@@ -509,6 +1933,7 @@ mod test {
     fn test_parses_gear_ratios() {
         let (_, gear_ratios) = find_all_part_numbers_and_gear_ratios(
             get_test_cases().into_iter().map(|(_, line)| line),
+            Adjacency::default(),
         );
 
         assert_eq!(gear_ratios, get_expected_gear_ratios());
@@ -518,10 +1943,384 @@ mod test {
     fn test_sums_gear_ratios() {
         let (_, gear_ratios) = find_all_part_numbers_and_gear_ratios(
             get_test_cases().into_iter().map(|(_, line)| line),
+            Adjacency::default(),
         );
 
-        let gear_ratios_sum: u32 = gear_ratios.iter().map(|g| g.0).sum();
+        let gear_ratios_sum: u64 = gear_ratios.iter().map(|g| g.0).sum();
 
         assert_eq!(gear_ratios_sum, 467835);
     }
+
+    /// The top left of the puzzle's example schematic
+    pub(crate) const SCHEMATIC: &str = "\
+467..114..
+...*......
+..35..633.
+......#...
+617*......";
+
+    pub(crate) fn parse_schematic(schematic: &str, rules: &SymbolRules) -> Vec<Line> {
+        schematic
+            .lines()
+            .enumerate()
+            .map(|(y, line)| parse_line_into_symbols_and_numbers(line, y, rules))
+            .collect()
+    }
+
+    #[test]
+    fn test_orthogonal_adjacency_skips_diagonals() {
+        let lines = parse_schematic(
+            "\
+12....
+..#...
+......
+.45...
+....#.
+......
+3$..7.
+....%.",
+            &SymbolRules::default(),
+        );
+
+        let (part_numbers, _) =
+            find_all_part_numbers_and_gear_ratios(lines.into_iter(), Adjacency::Orthogonal);
+        assert_eq!(part_numbers, vec![PartNumber(3), PartNumber(7)]);
+    }
+
+    #[test]
+    fn test_radius_adjacency_reaches_further_lines() {
+        let schematic = "\
+1.........
+..........
+..#.......
+..........
+.....9....
+..........
+..........
+........*.
+..........";
+
+        for (adjacency, expected) in [
+            (Adjacency::Radius(1), vec![]),
+            (Adjacency::Radius(2), vec![PartNumber(1)]),
+            (Adjacency::Radius(3), vec![PartNumber(1), PartNumber(9)]),
+        ] {
+            let lines = parse_schematic(schematic, &SymbolRules::default());
+            let (part_numbers, _) =
+                find_all_part_numbers_and_gear_ratios(lines.into_iter(), adjacency);
+            assert_eq!(
+                part_numbers, expected,
+                "Wrong part numbers for {:?}",
+                adjacency
+            );
+        }
+    }
+
+    #[test]
+    fn test_symbol_rules_from_table() {
+        let rules = "\
+# Blank cells
+blank .~
+# Gears and how many numbers they need
+gear * 3
+gear #@ 1
+"
+        .parse::<SymbolRules>()
+        .unwrap();
+        assert_eq!(
+            rules,
+            SymbolRules {
+                blanks: vec!['.', '~'],
+                gears: vec![('*', 3), ('#', 1), ('@', 1)],
+            }
+        );
+
+        let lines = parse_schematic(
+            "\
+2.3.4~~5
+.*..@..#
+6.......",
+            &rules,
+        );
+        assert_eq!(
+            lines[0].symbols,
+            vec![],
+            "~ should be blank with these rules"
+        );
+
+        let (part_numbers, gear_ratios) =
+            find_all_part_numbers_and_gear_ratios(lines.into_iter(), Adjacency::default());
+        assert_eq!(
+            part_numbers,
+            vec![
+                PartNumber(2),
+                PartNumber(3),
+                PartNumber(4),
+                PartNumber(5),
+                PartNumber(6)
+            ]
+        );
+        assert_eq!(gear_ratios, vec![GearRatio(36), GearRatio(4), GearRatio(5)]);
+
+        assert!("gear * x".parse::<SymbolRules>().is_err());
+        assert!("gear *".parse::<SymbolRules>().is_err());
+        assert!("blank 1".parse::<SymbolRules>().is_err());
+        assert!("blank .\ngear . 2".parse::<SymbolRules>().is_err());
+        assert!("wall #".parse::<SymbolRules>().is_err());
+        assert_eq!(
+            "blank .\ngear * 0".parse::<SymbolRules>(),
+            Err("LINE 1: A gear needs at least 1 adjacent number".to_string())
+        );
+        assert_eq!(
+            "gear * 2".parse::<SymbolRules>(),
+            Err("Expected a \"blank <chars>\" rule, e.g. \"blank .\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parses_adjacency() {
+        assert_eq!("4".parse(), Ok(Adjacency::Orthogonal));
+        assert_eq!("8".parse(), Ok(Adjacency::Radius(1)));
+        assert_eq!("radius:3".parse(), Ok(Adjacency::Radius(3)));
+        assert!("radius:x".parse::<Adjacency>().is_err());
+        assert!("radius:0".parse::<Adjacency>().is_err());
+        assert!("6".parse::<Adjacency>().is_err());
+    }
+
+    /// The original adjacency check, comparing every number in the window against
+    /// every symbol in it. Kept as the reference for the column index.
+    fn find_part_numbers_linear(
+        current_line: &Line,
+        nearby_lines: &[&Line],
+        adjacency: Adjacency,
+    ) -> Vec<PartNumber> {
+        let search_symbols: Vec<&SymbolNode> =
+            nearby_lines.iter().flat_map(|l| l.symbols.iter()).collect();
+
+        current_line
+            .numbers
+            .iter()
+            .filter(|number| {
+                search_symbols
+                    .iter()
+                    .any(|symbol| number.is_adjacent_to(symbol, adjacency))
+            })
+            .map(|number| PartNumber(number.value))
+            .collect()
+    }
+
+    fn find_gear_ratios_linear(
+        current_line: &Line,
+        nearby_lines: &[&Line],
+        adjacency: Adjacency,
+    ) -> Vec<GearRatio> {
+        let search_numbers: Vec<&NumberNode> =
+            nearby_lines.iter().flat_map(|l| l.numbers.iter()).collect();
+
+        let mut gear_ratios = vec![];
+        for gear_sym in current_line.symbols.iter() {
+            let SymbolType::Gear(required_numbers) = gear_sym.sym_type else {
+                continue;
+            };
+            let adjacent_numbers: Vec<&&NumberNode> = search_numbers
+                .iter()
+                .filter(|number| number.is_adjacent_to(gear_sym, adjacency))
+                .collect();
+            if adjacent_numbers.len() == required_numbers {
+                gear_ratios.push(GearRatio(
+                    adjacent_numbers
+                        .iter()
+                        .map(|number| number.value as u64)
+                        .product(),
+                ));
+            }
+        }
+        gear_ratios
+    }
+
+    /// Deterministic pseudo random schematic with short numbers, gears and other
+    /// symbols scattered over mostly blank cells
+    fn synthetic_schematic(width: usize, height: usize, seed: u64) -> Vec<String> {
+        let mut state = seed;
+        let mut next_random = move |bound: usize| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((state >> 33) as usize) % bound
+        };
+
+        (0..height)
+            .map(|_| {
+                (0..width)
+                    .map(|_| match next_random(20) {
+                        0..=5 => char::from(b'0' + next_random(10) as u8),
+                        6 => '*',
+                        7 => "#$%&+-/=@".chars().nth(next_random(9)).unwrap(),
+                        _ => '.',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Runs `find` over every line with the lines within reach around it
+    fn for_each_window<T>(
+        lines: &[Line],
+        adjacency: Adjacency,
+        find: impl Fn(&Line, &[&Line], Adjacency) -> Vec<T>,
+    ) -> Vec<T> {
+        (0..lines.len())
+            .flat_map(|y| find(&lines[y], &nearby_lines(lines, y, adjacency), adjacency))
+            .collect()
+    }
+
+    #[test]
+    fn test_column_index_agrees_with_linear_scan() {
+        let rules = "gear * 2\ngear @ 1\ngear % 3\nblank ."
+            .parse::<SymbolRules>()
+            .unwrap();
+        let lines = parse_schematic(&synthetic_schematic(2000, 30, 2023).join("\n"), &rules);
+
+        for adjacency in [
+            Adjacency::Orthogonal,
+            Adjacency::Radius(1),
+            Adjacency::Radius(2),
+            Adjacency::Radius(0),
+        ] {
+            assert_eq!(
+                for_each_window(&lines, adjacency, find_part_numbers),
+                for_each_window(&lines, adjacency, find_part_numbers_linear),
+                "Part numbers differ for {:?}",
+                adjacency
+            );
+            assert_eq!(
+                for_each_window(&lines, adjacency, find_gear_ratios),
+                for_each_window(&lines, adjacency, find_gear_ratios_linear),
+                "Gear ratios differ for {:?}",
+                adjacency
+            );
+        }
+    }
+
+    #[test]
+    fn test_looks_up_by_column() {
+        let line =
+            parse_line_into_symbols_and_numbers("12..*.345.#..6", 0, &SymbolRules::default());
+
+        assert_eq!(
+            line.symbols_in_columns(4, 10)
+                .iter()
+                .map(|symbol| symbol.x)
+                .collect::<Vec<_>>(),
+            vec![4, 10]
+        );
+        assert!(line.symbols_in_columns(5, 9).is_empty());
+        assert!(line.symbols_in_columns(11, 20).is_empty());
+        assert_eq!(
+            line.numbers_in_columns(1, 6)
+                .iter()
+                .map(|number| number.value)
+                .collect::<Vec<_>>(),
+            vec![12, 345]
+        );
+        assert_eq!(line.numbers_in_columns(13, 13)[0].value, 6);
+        assert!(line.numbers_in_columns(2, 5).is_empty());
+    }
+
+    /// Run with `cargo test --release -p day-03 -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_column_index_against_linear_scan() {
+        let lines = parse_schematic(
+            &synthetic_schematic(10_000, 200, 25).join("\n"),
+            &SymbolRules::default(),
+        );
+
+        for adjacency in [Adjacency::Radius(1), Adjacency::Radius(3)] {
+            let start = Instant::now();
+            let linear_part_numbers = for_each_window(&lines, adjacency, find_part_numbers_linear);
+            let linear_gear_ratios = for_each_window(&lines, adjacency, find_gear_ratios_linear);
+            let linear_time = start.elapsed();
+
+            let start = Instant::now();
+            let indexed_part_numbers = for_each_window(&lines, adjacency, find_part_numbers);
+            let indexed_gear_ratios = for_each_window(&lines, adjacency, find_gear_ratios);
+            let indexed_time = start.elapsed();
+
+            assert_eq!(linear_part_numbers, indexed_part_numbers);
+            assert_eq!(linear_gear_ratios, indexed_gear_ratios);
+            println!(
+                "10000x200 schematic, {:?}: linear scan {:?}, column index {:?}",
+                adjacency, linear_time, indexed_time
+            );
+        }
+    }
+
+    #[test]
+    fn test_parallel_bands_match_sequential() {
+        let rows = synthetic_schematic(300, 97, 3);
+        let rules = "gear * 2\ngear @ 1\nblank ."
+            .parse::<SymbolRules>()
+            .unwrap();
+        let lines = parse_schematic(&rows.join("\n"), &rules);
+        assert_eq!(parse_lines_in_parallel(&rows, &rules, 3, 10), lines);
+
+        for adjacency in [
+            Adjacency::Orthogonal,
+            Adjacency::Radius(1),
+            Adjacency::Radius(3),
+        ] {
+            let sequential = find_all_part_numbers_and_gear_ratios(
+                parse_schematic(&rows.join("\n"), &rules).into_iter(),
+                adjacency,
+            );
+            for (threads, band_rows) in [(1, 97), (4, 1), (4, 2), (3, 10), (8, 1000)] {
+                assert_eq!(
+                    find_all_part_numbers_and_gear_ratios_in_parallel(
+                        &lines, adjacency, threads, band_rows
+                    ),
+                    sequential,
+                    "{:?} with {} threads and {} rows per band",
+                    adjacency,
+                    threads,
+                    band_rows
+                );
+            }
+        }
+
+        assert_eq!(
+            find_all_part_numbers_and_gear_ratios_in_parallel(&[], Adjacency::default(), 4, 16),
+            (vec![], vec![])
+        );
+    }
+
+    /// Run with `cargo test --release -p day-03 -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_parallel_bands_on_tall_schematic() {
+        let rows = synthetic_schematic(140, 100_000, 7);
+        let rules = SymbolRules::default();
+
+        let start = Instant::now();
+        let sequential = find_all_part_numbers_and_gear_ratios(
+            rows.iter()
+                .enumerate()
+                .map(|(y, row)| parse_line_into_symbols_and_numbers(row, y, &rules)),
+            Adjacency::default(),
+        );
+        println!("100000 rows sequentially: {:?}", start.elapsed());
+
+        for threads in [1, 2, 4, 8] {
+            let start = Instant::now();
+            let lines = parse_lines_in_parallel(&rows, &rules, threads, 4096);
+            let parallel = find_all_part_numbers_and_gear_ratios_in_parallel(
+                &lines,
+                Adjacency::default(),
+                threads,
+                4096,
+            );
+            println!("100000 rows on {} threads: {:?}", threads, start.elapsed());
+            assert_eq!(parallel, sequential);
+        }
+    }
 }
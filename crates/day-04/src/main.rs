@@ -1,25 +1,116 @@
 use std::{
-    collections::{HashMap, HashSet},
-    iter::Peekable,
+    collections::{HashSet, VecDeque},
     ops::Deref,
+    str::FromStr,
 };
 
-struct CharAt {
-    column: usize,
-    c: char,
+use lexer::{LexError, Lexer};
+
+/// How many points a card is worth for its number of matches
+#[derive(Debug, PartialEq, Clone, Default)]
+enum ScoringRule {
+    /// 1 point for the first match, doubled for each match after it
+    #[default]
+    Doubling,
+    /// 1 point per match
+    Linear,
+    /// The Fibonacci number of the match count, so 1, 1, 2, 3, 5, ...
+    Fibonacci,
+    /// Points for 1, 2, 3, ... matches. More matches than entries is an error.
+    Table(Vec<u64>),
 }
-impl From<CharAt> for char {
-    fn from(value: CharAt) -> Self {
-        value.c
+impl ScoringRule {
+    fn points(&self, matches: usize) -> Result<u64, String> {
+        if matches == 0 {
+            return Ok(0);
+        }
+        let overflow_error = || format!("Points for {} matches overflowed u64", matches);
+        match self {
+            ScoringRule::Doubling => u32::try_from(matches - 1)
+                .ok()
+                .and_then(|shift| 1u64.checked_shl(shift))
+                .ok_or_else(overflow_error),
+            ScoringRule::Linear => Ok(matches as u64),
+            ScoringRule::Fibonacci => {
+                let (mut previous, mut current) = (0u64, 1u64);
+                for _ in 1..matches {
+                    (previous, current) = (
+                        current,
+                        previous.checked_add(current).ok_or_else(overflow_error)?,
+                    );
+                }
+                Ok(current)
+            }
+            ScoringRule::Table(points) => points.get(matches - 1).copied().ok_or_else(|| {
+                format!(
+                    "Points table only goes up to {} matches, got {}",
+                    points.len(),
+                    matches
+                )
+            }),
+        }
     }
 }
-impl Deref for CharAt {
-    type Target = char;
-    fn deref(&self) -> &Self::Target {
-        &self.c
+impl FromStr for ScoringRule {
+    type Err = String;
+
+    /// `doubling`, `linear`, `fibonacci` or `table:<points for 1 match>,<for 2>,...`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "doubling" => Ok(ScoringRule::Doubling),
+            "linear" => Ok(ScoringRule::Linear),
+            "fibonacci" => Ok(ScoringRule::Fibonacci),
+            _ => {
+                let table = s
+                    .strip_prefix("table:")
+                    .ok_or_else(|| format!("Unknown scoring rule \"{}\"", s))?;
+                table
+                    .split(',')
+                    .map(|points| {
+                        points.trim().parse::<u64>().map_err(|e| {
+                            format!("Invalid points \"{}\" in scoring table: {}", points, e)
+                        })
+                    })
+                    .collect::<Result<_, _>>()
+                    .map(ScoringRule::Table)
+            }
+        }
+    }
+}
+
+/// What to do with copies won of cards past the last card
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+enum PastLastCard {
+    /// Drop them, as there are no such cards
+    #[default]
+    Clamp,
+    /// Fail naming the card that won them
+    Error,
+    /// Count them in the total cards won. They win nothing themselves.
+    Allow,
+}
+impl FromStr for PastLastCard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(PastLastCard::Clamp),
+            "error" => Ok(PastLastCard::Error),
+            "allow" => Ok(PastLastCard::Allow),
+            _ => Err(format!(
+                "Unknown policy \"{}\", expected clamp, error or allow",
+                s
+            )),
+        }
     }
 }
 
+#[derive(Debug, PartialEq, Clone, Default)]
+struct Rules {
+    scoring: ScoringRule,
+    past_last_card: PastLastCard,
+}
+
 #[derive(Debug, PartialEq)]
 struct Card {
     number: u32,
@@ -27,14 +118,8 @@ struct Card {
     card_numbers: Vec<u32>,
 }
 impl Card {
-    fn points(&self) -> u32 {
-        let mut points = 0;
-        for card_number in &self.card_numbers {
-            if self.winning_numbers.contains(card_number) {
-                points = if points == 0 { 1 } else { points * 2 };
-            }
-        }
-        points
+    fn points(&self, rule: &ScoringRule) -> Result<u64, String> {
+        rule.points(self.matching_number_count())
     }
 
     fn matching_number_count(&self) -> usize {
@@ -47,212 +132,353 @@ impl Card {
 
 const CARD_HEADER: &str = "Card";
 
-fn get_error_msg_with_col(col_num: usize, msg: &str) -> String {
-    format!("COL {}: {}", col_num, msg)
-}
+fn parse_card_line(line_number: usize, line: &str) -> Result<Card, LexError> {
+    let mut lexer = Lexer::starting_at_line(line, line_number);
 
-fn get_parse_col(char_iter: &mut Peekable<impl Iterator<Item = CharAt>>) -> Result<usize, String> {
-    char_iter
-        .peek()
-        .map(|c| c.column)
-        .ok_or("Empty line while getting parse col".to_string())
-}
-
-fn try_consume_number(
-    char_iter: &mut Peekable<impl Iterator<Item = CharAt>>,
-) -> Result<u32, String> {
-    let start_col = get_parse_col(char_iter)?;
-
-    let mut number_str = String::new();
-    while let Some(c) = char_iter.peek() {
-        if !c.is_ascii_digit() {
-            break;
-        }
-        let c = char_iter.next().unwrap();
-        number_str.push(c.into());
+    if lexer.is_at_end() {
+        return Err(LexError::new(lexer.position(), "Empty line"));
     }
 
-    if number_str.is_empty() {
-        return Err(get_error_msg_with_col(start_col, "Expected number"));
-    }
+    lexer.try_consume_literal(CARD_HEADER)?;
+    lexer.try_consume_whitespace()?;
+    let card_number = lexer.try_consume_number()?;
+    lexer.try_consume_char(':')?;
 
-    number_str
-        .parse::<u32>()
-        .map_err(|e| get_error_msg_with_col(start_col, &format!("Failed to parse number: {}", e)))
-}
+    let winning_numbers: Vec<u32> = lexer.try_consume_list_of_numbers(Some('|'))?;
+    let card_numbers = lexer.try_consume_list_of_numbers(None)?;
 
-fn try_consume_whitespace(
-    char_iter: &mut Peekable<impl Iterator<Item = CharAt>>,
-) -> Result<usize, String> {
-    let start_col = get_parse_col(char_iter)?;
+    lexer.expect_end_of_line()?;
 
-    let mut whitespace_count = 0;
+    Ok(Card {
+        number: card_number,
+        winning_numbers: winning_numbers.into_iter().collect(),
+        card_numbers,
+    })
+}
 
-    while let Some(c) = char_iter.peek() {
-        if !c.is_whitespace() {
-            break;
-        }
-        char_iter.next();
-        whitespace_count += 1;
+struct PointsWon(u64);
+impl Deref for PointsWon {
+    type Target = u64;
+    fn deref(&self) -> &Self::Target {
+        &self.0
     }
+}
 
-    if whitespace_count == 0 {
-        return Err(get_error_msg_with_col(start_col, "Expected whitespace"));
+struct CardsWon(u64);
+impl Deref for CardsWon {
+    type Target = u64;
+    fn deref(&self) -> &Self::Target {
+        &self.0
     }
-
-    Ok(whitespace_count)
 }
 
-fn try_consume_list_of_numbers(
-    char_iter: &mut Peekable<impl Iterator<Item = CharAt>>,
-    end_of_list_char: Option<char>,
-) -> Result<Vec<u32>, String> {
-    let mut numbers = Vec::new();
-    loop {
-        if char_iter.peek().is_none() {
-            break;
-        }
-
-        let _ = try_consume_whitespace(char_iter)?;
-
-        match (char_iter.peek(), end_of_list_char) {
-            (Some(c), Some(end_of_list_char)) if c.deref() == &end_of_list_char => {
-                char_iter.next();
-                break;
-            }
-            (None, _) => break,
-            _ => {}
-        }
-
-        let number = try_consume_number(char_iter)?;
-        numbers.push(number);
+/// Cards must be numbered 1, 2, 3, ... in order, as copies are won by number
+fn check_card_number(previous: Option<u32>, number: u32) -> Result<(), String> {
+    let expected = previous.map_or(1, |previous| previous + 1);
+    if number == expected {
+        return Ok(());
+    }
+    match previous {
+        Some(previous) if number <= previous => Err(format!(
+            "Card {} is a duplicate or out of order, it follows card {}",
+            number, previous
+        )),
+        _ if number == expected + 1 => Err(format!(
+            "Card {} is missing before card {}",
+            expected, number
+        )),
+        _ => Err(format!(
+            "Cards {} to {} are missing before card {}",
+            expected,
+            number - 1,
+            number
+        )),
     }
-    Ok(numbers)
 }
 
-fn get_error_msg_with_line(line_num: usize, msg: &str) -> String {
-    format!("LINE {}: {}", line_num, msg)
+/// Copies of a card won by an earlier card
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct CopiesFrom {
+    card: u32,
+    copies: u64,
 }
 
-fn parse_card_line(line_number: usize, line: &str) -> Result<Card, String> {
-    let mut char_iter = line
-        .chars()
-        .enumerate()
-        .map(|(col, c)| CharAt { column: col, c })
-        .peekable();
-
-    if char_iter.peek().is_none() {
-        return Err(get_error_msg_with_line(line_number, "Empty line"));
+/// How a card scored and where its copies came from
+#[derive(Debug, PartialEq)]
+struct LedgerEntry {
+    card: u32,
+    matching_numbers: usize,
+    points: u64,
+    /// Copies won from earlier cards, not counting the original
+    copies_received: u64,
+    received_from: Vec<CopiesFrom>,
+}
+impl LedgerEntry {
+    fn total_copies(&self) -> u64 {
+        self.copies_received + 1
     }
+}
 
-    let first_4: String = char_iter.by_ref().take(4).map(|c| c.c).collect();
-    if first_4 != CARD_HEADER {
-        return Err(format!(
-            "Expected \"{}\" at start of line, got \"{}\"",
-            CARD_HEADER, first_4
-        ));
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum LedgerFormat {
+    Csv,
+    Json,
+}
+impl FromStr for LedgerFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(LedgerFormat::Csv),
+            "json" => Ok(LedgerFormat::Json),
+            _ => Err(format!(
+                "Unknown ledger format \"{}\", expected csv or json",
+                s
+            )),
+        }
     }
+}
 
-    let _ = try_consume_whitespace(&mut char_iter)
-        .map_err(|e| get_error_msg_with_line(line_number, &e))?;
-
-    let card_number =
-        try_consume_number(&mut char_iter).map_err(|e| get_error_msg_with_line(line_number, &e))?;
-
-    let next_char = char_iter.next().ok_or_else(|| {
-        get_error_msg_with_line(
-            line_number,
-            "Expected another char after card number, got end of line",
-        )
-    })?;
-    if *next_char != ':' {
-        return Err(get_error_msg_with_line(
-            line_number,
-            &format!("Expected \":\" after card number, got \"{}\"", *next_char),
+/// `received_from` is written as `card:copies` pairs separated by `;`
+fn format_ledger_csv(ledger: &[LedgerEntry]) -> String {
+    let mut out =
+        String::from("card,matching_numbers,points,copies_received,total_copies,received_from\n");
+    for entry in ledger {
+        let received_from: Vec<String> = entry
+            .received_from
+            .iter()
+            .map(|from| format!("{}:{}", from.card, from.copies))
+            .collect();
+        out.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            entry.card,
+            entry.matching_numbers,
+            entry.points,
+            entry.copies_received,
+            entry.total_copies(),
+            received_from.join(";")
         ));
     }
+    out
+}
 
-    let winning_numbers = try_consume_list_of_numbers(&mut char_iter, Some('|'))
-        .map_err(|e| get_error_msg_with_line(line_number, &e))?;
-    let card_numbers = try_consume_list_of_numbers(&mut char_iter, None)
-        .map_err(|e| get_error_msg_with_line(line_number, &e))?;
-
-    assert!(char_iter.next().is_none());
-
-    Ok(Card {
-        number: card_number,
-        winning_numbers: winning_numbers.into_iter().collect(),
-        card_numbers,
-    })
+fn format_ledger_json(ledger: &[LedgerEntry]) -> String {
+    let entries: Vec<String> = ledger
+        .iter()
+        .map(|entry| {
+            let received_from: Vec<String> = entry
+                .received_from
+                .iter()
+                .map(|from| format!("{{\"card\":{},\"copies\":{}}}", from.card, from.copies))
+                .collect();
+            format!(
+                "  {{\"card\":{},\"matching_numbers\":{},\"points\":{},\"copies_received\":{},\"total_copies\":{},\"received_from\":[{}]}}",
+                entry.card,
+                entry.matching_numbers,
+                entry.points,
+                entry.copies_received,
+                entry.total_copies(),
+                received_from.join(",")
+            )
+        })
+        .collect();
+    format!("[\n{}\n]\n", entries.join(",\n"))
 }
 
-struct PointsWon(u32);
-impl Deref for PointsWon {
-    type Target = u32;
-    fn deref(&self) -> &Self::Target {
-        &self.0
-    }
+/// Each card wins a copy of the next `matching_number_count` cards for every copy
+/// of it held. Copies won for the cards ahead are kept in a ring buffer that only
+/// grows to the largest matching count seen, with the front being the next card.
+/// Whatever is left in it at the end are copies of cards past the last card.
+fn calculate_points_and_total_cards(
+    cards: &mut impl Iterator<Item = Result<Card, String>>,
+    rules: &Rules,
+) -> Result<(PointsWon, CardsWon), String> {
+    play_cards(cards, rules, None)
 }
 
-struct CardsWon(u32);
-impl Deref for CardsWon {
-    type Target = u32;
-    fn deref(&self) -> &Self::Target {
-        &self.0
-    }
+/// Plays the cards like `calculate_points_and_total_cards`, also writing an entry
+/// for every card
+fn build_ledger(
+    cards: &mut impl Iterator<Item = Result<Card, String>>,
+    rules: &Rules,
+) -> Result<(Vec<LedgerEntry>, PointsWon, CardsWon), String> {
+    let mut ledger = vec![];
+    let (points_won, cards_won) = play_cards(cards, rules, Some(&mut ledger))?;
+    Ok((ledger, points_won, cards_won))
 }
 
-fn calculate_points_and_total_cards(
+/// When given a ledger, which earlier cards won the copies is kept in a second
+/// ring buffer alongside the copy counts
+fn play_cards(
     cards: &mut impl Iterator<Item = Result<Card, String>>,
+    rules: &Rules,
+    mut ledger: Option<&mut Vec<LedgerEntry>>,
 ) -> Result<(PointsWon, CardsWon), String> {
-    let mut num_copies_won = HashMap::new();
-
-    let mut total_cards = 0;
-    let mut total_points = 0;
+    let mut copies_won_ahead: VecDeque<u64> = VecDeque::new();
+    let mut sources_ahead: VecDeque<Vec<CopiesFrom>> = VecDeque::new();
+    // The card that won copies of the furthest card ahead, and that card's number
+    let mut furthest_card_won: Option<(u32, u64)> = None;
+    let mut last_card_number = None;
+
+    let mut total_cards: u64 = 0;
+    let mut total_points: u64 = 0;
     for card in cards {
         let card = card?;
+        check_card_number(last_card_number, card.number)?;
+        last_card_number = Some(card.number);
+        let overflow_error = |what: &str| format!("Card {}: {} overflowed u64", card.number, what);
+
+        let copies_received = copies_won_ahead.pop_front().unwrap_or(0);
+        let num_copies_of_this_card = copies_received
+            .checked_add(1)
+            .ok_or_else(|| overflow_error("copies won"))?;
 
-        let num_copies_of_this_card = *num_copies_won.entry(card.number).or_insert(0) + 1;
         let num_matching_numbers = card.matching_number_count();
+        if copies_won_ahead.len() < num_matching_numbers {
+            copies_won_ahead.resize(num_matching_numbers, 0);
+        }
+        for copies in copies_won_ahead.iter_mut().take(num_matching_numbers) {
+            *copies = copies
+                .checked_add(num_copies_of_this_card)
+                .ok_or_else(|| overflow_error("copies won"))?;
+        }
+        let furthest_won = card.number as u64 + num_matching_numbers as u64;
+        if furthest_card_won.is_none_or(|(_, furthest)| furthest_won > furthest) {
+            furthest_card_won = Some((card.number, furthest_won));
+        }
 
-        if num_matching_numbers > 0 {
-            for i in 1..=num_matching_numbers {
-                num_copies_won
-                    .entry(card.number + i as u32)
-                    .and_modify(|n| *n += num_copies_of_this_card)
-                    .or_insert(num_copies_of_this_card);
+        let points = card
+            .points(&rules.scoring)
+            .map_err(|e| format!("Card {}: {}", card.number, e))?;
+        total_points = total_points
+            .checked_add(points)
+            .ok_or_else(|| overflow_error("points"))?;
+        total_cards = total_cards
+            .checked_add(num_copies_of_this_card)
+            .ok_or_else(|| overflow_error("total cards"))?;
+
+        if let Some(ledger) = ledger.as_deref_mut() {
+            let received_from = sources_ahead.pop_front().unwrap_or_default();
+            if sources_ahead.len() < num_matching_numbers {
+                sources_ahead.resize(num_matching_numbers, vec![]);
+            }
+            for sources in sources_ahead.iter_mut().take(num_matching_numbers) {
+                sources.push(CopiesFrom {
+                    card: card.number,
+                    copies: num_copies_of_this_card,
+                });
             }
+
+            ledger.push(LedgerEntry {
+                card: card.number,
+                matching_numbers: num_matching_numbers,
+                points,
+                copies_received,
+                received_from,
+            });
         }
-        total_cards += num_copies_of_this_card;
-        total_points += card.points();
     }
-    Ok((
-        PointsWon(total_points),
-        CardsWon(total_cards.try_into().unwrap()),
-    ))
+
+    let copies_past_last_card: u64 = copies_won_ahead.iter().sum();
+    if copies_past_last_card > 0 {
+        match rules.past_last_card {
+            PastLastCard::Clamp => {}
+            PastLastCard::Error => {
+                let (winner, furthest) = furthest_card_won.unwrap();
+                return Err(format!(
+                    "Card {} won copies up to card {}, but the last card is card {}",
+                    winner,
+                    furthest,
+                    last_card_number.unwrap()
+                ));
+            }
+            PastLastCard::Allow => {
+                total_cards = total_cards
+                    .checked_add(copies_past_last_card)
+                    .ok_or("Total cards overflowed u64")?;
+            }
+        }
+    }
+
+    Ok((PointsWon(total_points), CardsWon(total_cards)))
+}
+
+struct Args {
+    rules: Rules,
+    /// Print the per-card ledger instead of the totals
+    ledger_format: Option<LedgerFormat>,
+}
+
+/// Usage: `day-04 [--scoring <doubling|linear|fibonacci|table:N,N,...>]
+/// [--past-last-card <clamp|error|allow>] [--ledger <csv|json>]`
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut rules = Rules::default();
+    let mut ledger_format = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scoring" => {
+                rules.scoring = args
+                    .next()
+                    .ok_or("Expected a scoring rule after --scoring")?
+                    .parse()?
+            }
+            "--past-last-card" => {
+                rules.past_last_card = args
+                    .next()
+                    .ok_or("Expected a policy after --past-last-card")?
+                    .parse()?
+            }
+            "--ledger" => {
+                ledger_format = Some(
+                    args.next()
+                        .ok_or("Expected a format after --ledger")?
+                        .parse()?,
+                )
+            }
+            _ => return Err(format!("Unknown argument \"{}\"", arg)),
+        }
+    }
+    Ok(Args {
+        rules,
+        ledger_format,
+    })
 }
 
 fn main() {
+    let args = parse_args(std::env::args().skip(1)).unwrap_or_else(|e| panic!("{}", e));
+
     let mut cards = std::io::stdin()
         .lines()
         .enumerate()
         .map(|(line_num, line)| {
             let line = line.unwrap();
-            parse_card_line(line_num, &line)
-                .map_err(|e| format!("Error parsing line {}: {}", line_num, e))
+            parse_card_line(line_num, &line).map_err(|e| format!("Error parsing card: {}", e))
         });
 
-    let (points_won, cards_won) =
-        calculate_points_and_total_cards(&mut cards).expect("Failed to calculate cards won");
+    if let Some(format) = args.ledger_format {
+        let (ledger, _, _) =
+            build_ledger(&mut cards, &args.rules).expect("Failed to build card ledger");
+        match format {
+            LedgerFormat::Csv => print!("{}", format_ledger_csv(&ledger)),
+            LedgerFormat::Json => print!("{}", format_ledger_json(&ledger)),
+        }
+        return;
+    }
+
+    let (points_won, cards_won) = calculate_points_and_total_cards(&mut cards, &args.rules)
+        .expect("Failed to calculate cards won");
     println!("Points won: {}", *points_won);
     println!("Cards won: {}", *cards_won);
 }
 
 #[cfg(test)]
 mod test {
-    use std::collections::HashSet;
+    use std::collections::{HashMap, HashSet};
 
-    use crate::Card;
+    use crate::{
+        build_ledger, calculate_points_and_total_cards, format_ledger_csv, format_ledger_json,
+        Card, CopiesFrom, LedgerEntry, PastLastCard, Rules, ScoringRule,
+    };
 
     fn get_test_cases() -> Vec<(&'static str, Card)> {
         vec![
@@ -326,7 +552,10 @@ mod test {
             .into_iter()
             .map(|(_, card)| card)
             .collect::<Vec<_>>();
-        let points_sum = cards.iter().map(|card| card.points()).sum::<u32>();
+        let points_sum = cards
+            .iter()
+            .map(|card| card.points(&ScoringRule::Doubling).unwrap())
+            .sum::<u64>();
         assert_eq!(points_sum, 13);
     }
 
@@ -334,8 +563,219 @@ mod test {
     fn test_cards_won() {
         let mut cards = get_test_cases().into_iter().map(|(_, card)| Ok(card));
 
-        let (_, cards_won) = super::calculate_points_and_total_cards(&mut cards).unwrap();
+        let (_, cards_won) =
+            super::calculate_points_and_total_cards(&mut cards, &Rules::default()).unwrap();
 
         assert_eq!(*cards_won, 30);
     }
+
+    #[test]
+    fn test_reports_error_positions() {
+        let error = |line: &str| super::parse_card_line(4, line).unwrap_err().to_string();
+
+        assert_eq!(error(""), "LINE 4: COL 0: Empty line");
+        assert_eq!(
+            error("Crad 1: 1 | 2"),
+            "LINE 4: COL 0: Expected \"Card\", got word \"Crad\""
+        );
+        assert_eq!(
+            error("Card 1 | 2"),
+            "LINE 4: COL 6: Expected \":\", got whitespace"
+        );
+        assert_eq!(
+            error("Card 1: 41 x8 | 2"),
+            "LINE 4: COL 11: Expected number, got word \"x\""
+        );
+        assert_eq!(
+            error("Card 1: 41 | 2,"),
+            "LINE 4: COL 14: Expected whitespace, got \",\""
+        );
+    }
+
+    /// The original card counting, keyed by card number in a map that keeps
+    /// every card seen
+    fn reference_total_cards(cards: &[Card]) -> u64 {
+        let mut num_copies_won: HashMap<u32, u64> = HashMap::new();
+        let mut total_cards = 0;
+        for card in cards {
+            let num_copies_of_this_card = *num_copies_won.entry(card.number).or_insert(0) + 1;
+            for i in 1..=card.matching_number_count() {
+                *num_copies_won.entry(card.number + i as u32).or_insert(0) +=
+                    num_copies_of_this_card;
+            }
+            total_cards += num_copies_of_this_card;
+        }
+        total_cards
+    }
+
+    /// Card `number` with its first `matches` numbers winning
+    fn card_with_matches(number: u32, matches: u32) -> Card {
+        Card {
+            number,
+            winning_numbers: (0..matches).collect(),
+            card_numbers: (0..10).collect(),
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer_matches_reference() {
+        let mut state: u64 = 4;
+        let cards: Vec<Card> = (1..=500)
+            .map(|number| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let matches = if (state >> 33).is_multiple_of(4) {
+                    (state >> 40) % 6
+                } else {
+                    0
+                };
+                card_with_matches(number, matches as u32)
+            })
+            .collect();
+        let expected = reference_total_cards(&cards);
+
+        let (_, cards_won) =
+            calculate_points_and_total_cards(&mut cards.into_iter().map(Ok), &Rules::default())
+                .unwrap();
+        assert_eq!(*cards_won, expected);
+    }
+
+    #[test]
+    fn test_reports_overflow_instead_of_panicking() {
+        // Copies grow like the Fibonacci numbers when every card wins the next two
+        let mut cards = (1..=120).map(|number| Ok(card_with_matches(number, 2)));
+        let error = calculate_points_and_total_cards(&mut cards, &Rules::default())
+            .err()
+            .expect("Copies should overflow u64");
+        assert!(error.contains("overflowed u64"), "{}", error);
+    }
+
+    #[test]
+    fn test_scoring_rules() {
+        let points = |rule: &str| -> Vec<u64> {
+            let rule = rule.parse::<ScoringRule>().unwrap();
+            (0..=5)
+                .map(|matches| rule.points(matches).unwrap())
+                .collect()
+        };
+        assert_eq!(points("doubling"), vec![0, 1, 2, 4, 8, 16]);
+        assert_eq!(points("linear"), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(points("fibonacci"), vec![0, 1, 1, 2, 3, 5]);
+        assert_eq!(points("table:1, 3,5,10,20"), vec![0, 1, 3, 5, 10, 20]);
+
+        assert!(ScoringRule::Table(vec![1, 2]).points(3).is_err());
+        assert!(ScoringRule::Doubling.points(65).is_err());
+        assert!(ScoringRule::Fibonacci.points(100).is_err());
+        assert!("table:1,x".parse::<ScoringRule>().is_err());
+        assert!("squares".parse::<ScoringRule>().is_err());
+
+        let mut cards = get_test_cases().into_iter().map(|(_, card)| Ok(card));
+        let rules = Rules {
+            scoring: ScoringRule::Linear,
+            ..Rules::default()
+        };
+        let (points_won, _) = calculate_points_and_total_cards(&mut cards, &rules).unwrap();
+        assert_eq!(*points_won, 4 + 2 + 2 + 1);
+    }
+
+    #[test]
+    fn test_past_last_card_policies() {
+        // Card 2 wins copies of cards 3 and 4, but card 3 is the last
+        let cards = || {
+            [(1, 1), (2, 2), (3, 0)]
+                .into_iter()
+                .map(|(number, matches)| Ok(card_with_matches(number, matches)))
+        };
+        let total_cards = |past_last_card| {
+            let rules = Rules {
+                past_last_card,
+                ..Rules::default()
+            };
+            calculate_points_and_total_cards(&mut cards(), &rules).map(|(_, cards_won)| *cards_won)
+        };
+
+        assert_eq!(total_cards(PastLastCard::Clamp), Ok(1 + 2 + 3));
+        assert_eq!(total_cards(PastLastCard::Allow), Ok(1 + 2 + 3 + 2));
+        assert_eq!(
+            total_cards(PastLastCard::Error),
+            Err("Card 2 won copies up to card 4, but the last card is card 3".to_string())
+        );
+        assert_eq!("allow".parse(), Ok(PastLastCard::Allow));
+        assert!("wrap".parse::<PastLastCard>().is_err());
+    }
+
+    #[test]
+    fn test_reports_gaps_and_duplicates() {
+        let total_cards = |numbers: &[u32]| {
+            let mut cards = numbers
+                .iter()
+                .map(|number| Ok(card_with_matches(*number, 1)));
+            calculate_points_and_total_cards(&mut cards, &Rules::default())
+                .map(|(_, cards_won)| *cards_won)
+        };
+
+        assert_eq!(total_cards(&[1, 2, 3]), Ok(1 + 2 + 3));
+        assert_eq!(
+            total_cards(&[1, 2, 2]),
+            Err("Card 2 is a duplicate or out of order, it follows card 2".to_string())
+        );
+        assert_eq!(
+            total_cards(&[1, 3]),
+            Err("Card 2 is missing before card 3".to_string())
+        );
+        assert_eq!(
+            total_cards(&[1, 2, 6]),
+            Err("Cards 3 to 5 are missing before card 6".to_string())
+        );
+        assert_eq!(
+            total_cards(&[2]),
+            Err("Card 1 is missing before card 2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_builds_copy_ledger() {
+        let mut cards = get_test_cases().into_iter().map(|(_, card)| Ok(card));
+        let (ledger, points_won, cards_won) = build_ledger(&mut cards, &Rules::default()).unwrap();
+        assert_eq!((*points_won, *cards_won), (13, 30));
+        assert_eq!(
+            ledger.iter().map(|entry| entry.total_copies()).sum::<u64>(),
+            30
+        );
+
+        let from = |card, copies| CopiesFrom { card, copies };
+        assert_eq!(
+            ledger[3],
+            LedgerEntry {
+                card: 4,
+                matching_numbers: 1,
+                points: 1,
+                copies_received: 7,
+                received_from: vec![from(1, 1), from(2, 2), from(3, 4)],
+            }
+        );
+
+        assert_eq!(
+            format_ledger_csv(&ledger),
+            "\
+card,matching_numbers,points,copies_received,total_copies,received_from
+1,4,8,0,1,
+2,2,2,1,2,1:1
+3,2,2,3,4,1:1;2:2
+4,1,1,7,8,1:1;2:2;3:4
+5,0,0,13,14,1:1;3:4;4:8
+6,0,0,0,1,
+"
+        );
+        assert_eq!(
+            format_ledger_json(&ledger[..2]),
+            "\
+[
+  {\"card\":1,\"matching_numbers\":4,\"points\":8,\"copies_received\":0,\"total_copies\":1,\"received_from\":[]},
+  {\"card\":2,\"matching_numbers\":2,\"points\":2,\"copies_received\":1,\"total_copies\":2,\"received_from\":[{\"card\":1,\"copies\":1}]}
+]
+"
+        );
+    }
 }
@@ -1,6 +1,9 @@
 use std::time::SystemTime;
 
-use day5::parse::parse_input_into_mappings;
+use day5::{
+    parse::parse_input_into_mappings,
+    types::{RangeInterval, SeedInterpretation},
+};
 
 mod day5 {
     pub(crate) mod types {
@@ -19,6 +22,10 @@ mod day5 {
                 self.1
             }
 
+            pub(crate) fn is_empty(&self) -> bool {
+                self.start() >= self.end()
+            }
+
             pub(crate) fn intersect(&self, other: &Self) -> Option<Self> {
                 let start = self.start().max(other.start());
                 let end = self.end().min(other.end());
@@ -82,6 +89,92 @@ mod day5 {
             }
         }
 
+        /// How the numbers on the `seeds:` line are read
+        #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+        pub(crate) enum SeedInterpretation {
+            /// Every number is one seed (Part 1)
+            Individual,
+            /// Numbers are (start, length) pairs (Part 2)
+            Ranges,
+        }
+        impl SeedInterpretation {
+            pub(crate) fn label(&self) -> &'static str {
+                match self {
+                    SeedInterpretation::Individual => "Part 1 (individual seeds)",
+                    SeedInterpretation::Ranges => "Part 2 (seed ranges)",
+                }
+            }
+        }
+        impl std::str::FromStr for SeedInterpretation {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    "1" | "individual" => Ok(SeedInterpretation::Individual),
+                    "2" | "ranges" => Ok(SeedInterpretation::Ranges),
+                    _ => Err(format!(
+                        "Unknown seed interpretation \"{}\", expected individual or ranges",
+                        s
+                    )),
+                }
+            }
+        }
+
+        /// The numbers on the `seeds:` line, before deciding how to read them
+        #[derive(Debug, PartialEq, Eq)]
+        pub(crate) struct Seeds {
+            line_num: usize,
+            numbers: Vec<u64>,
+        }
+        impl Seeds {
+            pub(super) fn new(line_num: usize, numbers: Vec<u64>) -> Self {
+                Self { line_num, numbers }
+            }
+
+            pub(crate) fn intervals(
+                &self,
+                interpretation: SeedInterpretation,
+            ) -> Result<Vec<RangeInterval>, String> {
+                match interpretation {
+                    SeedInterpretation::Individual => self
+                        .numbers
+                        .iter()
+                        .map(|seed| {
+                            seed.checked_add(1)
+                                .map(|end| RangeInterval::new(*seed, end))
+                                .ok_or_else(|| self.error(&format!("Seed {} overflows", seed)))
+                        })
+                        .collect(),
+                    SeedInterpretation::Ranges => {
+                        if !self.numbers.len().is_multiple_of(2) {
+                            return Err(self.error(&format!(
+                                "Expected even number of seed numbers, got {}",
+                                self.numbers.len()
+                            )));
+                        }
+                        self.numbers
+                            .chunks(2)
+                            .map(|start_and_len| {
+                                start_and_len[0]
+                                    .checked_add(start_and_len[1])
+                                    .map(|end| RangeInterval::new(start_and_len[0], end))
+                                    .ok_or_else(|| {
+                                        self.error(&format!(
+                                            "Seed range start {} + length {} overflows",
+                                            start_and_len[0], start_and_len[1]
+                                        ))
+                                    })
+                            })
+                            .collect()
+                    }
+                }
+            }
+
+            fn error(&self, msg: &str) -> String {
+                format!("LINE {}: {}", self.line_num, msg)
+            }
+        }
+
         #[derive(PartialEq, Eq, Debug, PartialOrd, Ord)]
         pub(crate) struct RangeMapping {
             source: RangeInterval,
@@ -122,6 +215,19 @@ mod day5 {
                 })
             }
 
+            /// The same mapping run backwards, from destination to source
+            fn inverted(&self) -> Self {
+                Self {
+                    source: self.dest.clone(),
+                    dest: self.source.clone(),
+                }
+            }
+
+            /// How far the mapping moves a value
+            fn offset(&self) -> i128 {
+                self.dest.start() as i128 - self.source.start() as i128
+            }
+
             pub(super) fn map_input_range(
                 &self,
                 input: &RangeInterval,
@@ -187,6 +293,56 @@ mod day5 {
                 Ok(())
             }
 
+            /// The map as pieces covering every value in order, with the gaps between
+            /// range mappings filled by identity pieces
+            fn pieces(&self) -> Vec<Piece> {
+                let mut pieces = vec![];
+                let mut next_start = 0;
+                for mapping in self.range_mappings.iter() {
+                    if mapping.source.start() == mapping.source.end() {
+                        continue;
+                    }
+                    if next_start < mapping.source.start() {
+                        pieces.push(Piece::identity(next_start, mapping.source.start()));
+                    }
+                    pieces.push(Piece {
+                        source: mapping.source.clone(),
+                        offset: mapping.offset(),
+                    });
+                    next_start = mapping.source.end();
+                }
+                if next_start < u64::MAX {
+                    pieces.push(Piece::identity(next_start, u64::MAX));
+                }
+                pieces
+            }
+
+            /// Every input that the map sends into `output`, as pieces with the offset
+            /// from input to output. Each range mapping is inverted to find the inputs
+            /// it sends there, and the parts of `output` outside every source range
+            /// come from themselves.
+            fn preimage(&self, output: &RangeInterval) -> Vec<Piece> {
+                let mut preimage = vec![];
+                let mut identity_outputs = vec![output.clone()];
+                for mapping in self.range_mappings.iter() {
+                    if let (Some(inputs), _) = mapping.inverted().map_input_range(output) {
+                        preimage.push(Piece {
+                            source: inputs,
+                            offset: mapping.offset(),
+                        });
+                    }
+                    identity_outputs = identity_outputs
+                        .iter()
+                        .flat_map(|range| mapping.map_input_range(range).1 .0)
+                        .collect();
+                }
+                preimage.extend(identity_outputs.into_iter().map(|range| Piece {
+                    source: range,
+                    offset: 0,
+                }));
+                preimage
+            }
+
             pub(crate) fn map_input_range(&self, input_range: RangeInterval) -> Vec<RangeInterval> {
                 let mut all_unmapped_input = vec![input_range];
 
@@ -238,13 +394,89 @@ mod day5 {
                 merged_ranges
             }
 
-            pub(crate) fn get_lowest_final_mapping(
+            /// Every seed that ends up in `locations`, as pieces with the offset from
+            /// seed to location, found by running the maps in reverse
+            fn inverse_pieces(&self, locations: &RangeInterval) -> Vec<Piece> {
+                let mut pieces = vec![Piece {
+                    source: locations.clone(),
+                    offset: 0,
+                }];
+                for mapping in self.mappings.iter().rev() {
+                    pieces = pieces
+                        .iter()
+                        .flat_map(|piece| {
+                            mapping
+                                .preimage(&piece.source)
+                                .into_iter()
+                                .map(|preimage| Piece {
+                                    source: preimage.source,
+                                    offset: preimage.offset + piece.offset,
+                                })
+                        })
+                        .collect();
+                }
+                pieces
+            }
+
+            /// The seed ranges that lead to any location in `locations`, sorted and
+            /// with touching ranges joined
+            pub(crate) fn seeds_leading_to(&self, locations: &RangeInterval) -> Vec<RangeInterval> {
+                let mut seed_ranges: Vec<RangeInterval> = self
+                    .inverse_pieces(locations)
+                    .into_iter()
+                    .map(|piece| piece.source)
+                    .collect();
+                seed_ranges.sort();
+
+                let mut joined: Vec<RangeInterval> = vec![];
+                for range in seed_ranges {
+                    match joined.last_mut() {
+                        Some(last) if range.start() <= last.end() => {
+                            *last = RangeInterval(last.start(), last.end().max(range.end()));
+                        }
+                        _ => joined.push(range),
+                    }
+                }
+                joined
+            }
+
+            /// The lowest location reachable from the seeds, found by tracing every
+            /// location back to the seeds it comes from instead of mapping the seeds
+            /// forwards
+            pub(crate) fn get_lowest_reachable_location(
                 &self,
                 seeds: &[RangeInterval],
             ) -> Result<u64, String> {
-                if seeds.is_empty() {
-                    return Err("No seed ranges provided".to_string());
+                let seeds = non_empty_seed_ranges(seeds)?;
+
+                self.inverse_pieces(&RangeInterval(0, u64::MAX))
+                    .iter()
+                    .flat_map(|piece| {
+                        seeds.iter().filter_map(|seed_range| {
+                            piece
+                                .source
+                                .intersect(seed_range)
+                                .map(|overlap| piece.apply(overlap.start()))
+                        })
+                    })
+                    .min()
+                    .ok_or_else(|| "Seed ranges are all empty".to_string())
+            }
+
+            /// Folds every map into one piecewise-offset function
+            pub(crate) fn compile(&self) -> CompiledPipeline {
+                let mut pieces = vec![Piece::identity(0, u64::MAX)];
+                for mapping in self.mappings.iter() {
+                    pieces = Piece::compose(&pieces, &mapping.pieces());
                 }
+                CompiledPipeline { pieces }
+            }
+
+            pub(crate) fn get_lowest_final_mapping(
+                &self,
+                seeds: &[RangeInterval],
+            ) -> Result<u64, String> {
+                let seeds = non_empty_seed_ranges(seeds)?;
 
                 let mut lowest = u64::MAX;
 
@@ -267,8 +499,256 @@ mod day5 {
             }
         }
 
+        /// Drops seed ranges with no seeds in them. Errors when that leaves none, as
+        /// there is then no lowest location.
+        fn non_empty_seed_ranges(seeds: &[RangeInterval]) -> Result<Vec<RangeInterval>, String> {
+            if seeds.is_empty() {
+                return Err("No seed ranges provided".to_string());
+            }
+            let non_empty: Vec<RangeInterval> = seeds
+                .iter()
+                .filter(|range| !range.is_empty())
+                .cloned()
+                .collect();
+            if non_empty.is_empty() {
+                return Err("Seed ranges are all empty".to_string());
+            }
+            Ok(non_empty)
+        }
+
+        /// Values in `source` map to themselves plus `offset`
+        #[derive(Debug, PartialEq, Eq, Clone)]
+        struct Piece {
+            source: RangeInterval,
+            offset: i128,
+        }
+        impl Piece {
+            fn identity(start: u64, end: u64) -> Self {
+                Self {
+                    source: RangeInterval(start, end),
+                    offset: 0,
+                }
+            }
+
+            fn apply(&self, value: u64) -> u64 {
+                (value as i128 + self.offset) as u64
+            }
+
+            /// Applies `first` and then `second`. Both must cover every value, and so
+            /// does the result. Neighbouring pieces with the same offset are joined.
+            fn compose(first: &[Piece], second: &[Piece]) -> Vec<Piece> {
+                let mut composed: Vec<Piece> = vec![];
+                for piece in first {
+                    let image = RangeInterval(
+                        piece.apply(piece.source.start()),
+                        piece.apply(piece.source.end()),
+                    );
+
+                    let mut idx = second.partition_point(|next| next.source.end() <= image.start());
+                    while let Some(next) = second
+                        .get(idx)
+                        .filter(|next| next.source.start() < image.end())
+                    {
+                        let overlap = next.source.intersect(&image).unwrap();
+                        let source = RangeInterval(
+                            (overlap.start() as i128 - piece.offset) as u64,
+                            (overlap.end() as i128 - piece.offset) as u64,
+                        );
+                        let offset = piece.offset + next.offset;
+
+                        match composed.last_mut() {
+                            Some(last)
+                                if last.offset == offset && last.source.end() == source.start() =>
+                            {
+                                last.source = RangeInterval(last.source.start(), source.end());
+                            }
+                            _ => composed.push(Piece { source, offset }),
+                        }
+                        idx += 1;
+                    }
+                }
+                composed
+            }
+        }
+
+        /// A `MappingPipeline` folded into sorted pieces that together cover every
+        /// value, so each value goes through one piece instead of every map
+        pub(crate) struct CompiledPipeline {
+            pieces: Vec<Piece>,
+        }
+        impl CompiledPipeline {
+            /// Sweeps the pieces once over the seed ranges in order. The lowest value
+            /// of each range within a piece is where that piece starts mapping it.
+            pub(crate) fn get_lowest_final_mapping(
+                &self,
+                seeds: &[RangeInterval],
+            ) -> Result<u64, String> {
+                let mut seeds = non_empty_seed_ranges(seeds)?;
+                seeds.sort();
+
+                let mut lowest = u64::MAX;
+                let mut first_piece = 0;
+                for seed_range in seeds.iter() {
+                    while self.pieces[first_piece].source.end() <= seed_range.start() {
+                        first_piece += 1;
+                    }
+                    for piece in self.pieces[first_piece..]
+                        .iter()
+                        .take_while(|piece| piece.source.start() < seed_range.end())
+                    {
+                        let start = piece.source.start().max(seed_range.start());
+                        lowest = lowest.min(piece.apply(start));
+                    }
+                }
+
+                Ok(lowest)
+            }
+        }
+
         #[cfg(test)]
-        mod test {}
+        mod test {
+            use super::{
+                CompiledPipeline, MappingPipeline, RangeInterval, RangeMapping, SourceToDestMap,
+            };
+
+            /// Deterministic pseudo random numbers below `bound`
+            struct Lcg(u64);
+            impl Lcg {
+                fn next(&mut self, bound: u64) -> u64 {
+                    self.0 = self
+                        .0
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    (self.0 >> 33) % bound
+                }
+            }
+
+            /// Maps with non overlapping sources over a small range of values, so
+            /// ranges often land on gaps and edges
+            fn random_pipeline(rng: &mut Lcg) -> MappingPipeline {
+                let maps = (0..rng.next(6) + 1)
+                    .map(|map_idx| {
+                        let mut source_start = rng.next(10);
+                        let mut range_mappings = vec![];
+                        for _ in 0..rng.next(5) {
+                            let length = rng.next(30);
+                            range_mappings.push(
+                                RangeMapping::new(rng.next(200), source_start, length as usize)
+                                    .unwrap(),
+                            );
+                            source_start += length + rng.next(20);
+                        }
+                        SourceToDestMap::new(format!("map-{}", map_idx), range_mappings).unwrap()
+                    })
+                    .collect();
+                MappingPipeline::new(maps)
+            }
+
+            /// Where a single seed ends up, found with one binary search. The pieces
+            /// end at `u64::MAX`, so `value` must be below it.
+            fn map_value(compiled: &CompiledPipeline, value: u64) -> u64 {
+                let idx = compiled
+                    .pieces
+                    .partition_point(|piece| piece.source.end() <= value);
+                compiled.pieces[idx].apply(value)
+            }
+
+            /// A few seed ranges over the same small range of values as
+            /// `random_pipeline`. Some ranges are empty, and sometimes all of them are.
+            fn random_seed_ranges(rng: &mut Lcg) -> Vec<RangeInterval> {
+                (0..rng.next(4) + 1)
+                    .map(|_| {
+                        let start = rng.next(250);
+                        RangeInterval::new(start, start + rng.next(60))
+                    })
+                    .collect()
+            }
+
+            #[test]
+            fn test_compiled_pipeline_matches_staged_evaluation() {
+                let mut rng = Lcg(5);
+                for _ in 0..300 {
+                    let pipeline = random_pipeline(&mut rng);
+                    let compiled = pipeline.compile();
+
+                    for seed in 0..250 {
+                        assert_eq!(
+                            Ok(map_value(&compiled, seed)),
+                            pipeline
+                                .get_lowest_final_mapping(&[RangeInterval::new(seed, seed + 1)]),
+                            "Seed {} maps differently",
+                            seed
+                        );
+                    }
+
+                    let seed_ranges = random_seed_ranges(&mut rng);
+                    assert_eq!(
+                        compiled.get_lowest_final_mapping(&seed_ranges),
+                        pipeline.get_lowest_final_mapping(&seed_ranges),
+                        "Seed ranges {:?} map differently",
+                        seed_ranges
+                    );
+                }
+            }
+
+            #[test]
+            fn test_inverse_pipeline_matches_forward_mapping() {
+                let mut rng = Lcg(17);
+                for _ in 0..300 {
+                    let pipeline = random_pipeline(&mut rng);
+                    let compiled = pipeline.compile();
+
+                    let start = rng.next(250);
+                    let locations = RangeInterval::new(start, start + rng.next(40) + 1);
+                    let seed_ranges = pipeline.seeds_leading_to(&locations);
+                    for seed in 0..300 {
+                        let location = map_value(&compiled, seed);
+                        let leads_to_locations =
+                            location >= locations.start() && location < locations.end();
+                        let found_by_inverse = seed_ranges
+                            .iter()
+                            .any(|range| seed >= range.start() && seed < range.end());
+                        assert_eq!(
+                            found_by_inverse, leads_to_locations,
+                            "Seed {} goes to location {}, inverse found {:?} for {:?}",
+                            seed, location, seed_ranges, locations
+                        );
+                    }
+
+                    let seeds = random_seed_ranges(&mut rng);
+                    assert_eq!(
+                        pipeline.get_lowest_reachable_location(&seeds),
+                        pipeline.get_lowest_final_mapping(&seeds),
+                        "Seed ranges {:?} map differently",
+                        seeds
+                    );
+                }
+            }
+
+            #[test]
+            fn test_compiled_pieces_cover_every_value() {
+                let mut rng = Lcg(11);
+                for _ in 0..100 {
+                    let compiled = random_pipeline(&mut rng).compile();
+                    assert_eq!(compiled.pieces.first().unwrap().source.start(), 0);
+                    assert_eq!(compiled.pieces.last().unwrap().source.end(), u64::MAX);
+                    for pair in compiled.pieces.windows(2) {
+                        assert_eq!(pair[0].source.end(), pair[1].source.start());
+                        assert_ne!(pair[0].offset, pair[1].offset);
+                    }
+                }
+
+                let top = RangeMapping::new(0, u64::MAX - 10, 10).unwrap();
+                let compiled =
+                    MappingPipeline::new(vec![
+                        SourceToDestMap::new("top".to_string(), vec![top]).unwrap()
+                    ])
+                    .compile();
+                assert_eq!(map_value(&compiled, u64::MAX - 3), 7);
+                assert_eq!(map_value(&compiled, u64::MAX - 11), u64::MAX - 11);
+                assert!(compiled.get_lowest_final_mapping(&[]).is_err());
+            }
+        }
     }
 
     pub(crate) mod parse {
@@ -418,7 +898,7 @@ mod day5 {
 
         pub(crate) fn parse_input_into_mappings<'a>(
             lines: impl Iterator<Item = String> + 'a,
-        ) -> Result<(Vec<RangeInterval>, MappingPipeline), String> {
+        ) -> Result<(Seeds, MappingPipeline), String> {
             let mut lines = InputLines::new(lines);
 
             let line = lines
@@ -436,33 +916,87 @@ mod day5 {
 
             let seed_numbers = try_consume_list_of_numbers(number_list_str)
                 .map_err(|e| get_error_msg_with_line(line.line_num, &e))?;
-            if seed_numbers.len() % 2 != 0 {
-                return Err(get_error_msg_with_line(
-                    line.line_num,
-                    &format!(
-                        "Expected even number of seed numbers, got {}",
-                        seed_numbers.len()
-                    ),
-                ));
-            }
-            let seed_ranges = seed_numbers
-                .as_slice()
-                .chunks(2)
-                .map(|start_and_end| {
-                    RangeInterval::new(start_and_end[0], start_and_end[0] + start_and_end[1])
-                })
-                .collect();
+            let seeds = Seeds::new(line.line_num, seed_numbers);
 
             try_consume_empty_line(&mut lines)?;
 
             let maps = parse_maps(&mut lines)?;
 
-            Ok((seed_ranges, MappingPipeline::new(maps)))
+            Ok((seeds, MappingPipeline::new(maps)))
+        }
+    }
+}
+
+/// How the lowest location is found
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Evaluation {
+    /// Fold the maps into one function first
+    Compiled,
+    /// Run the seeds through each map in turn
+    Staged,
+    /// Trace locations back through the inverted maps
+    Inverse,
+}
+
+struct Args {
+    interpretations: Vec<SeedInterpretation>,
+    evaluation: Evaluation,
+    /// Location ranges to trace back to the seeds they come from
+    origins: Vec<RangeInterval>,
+}
+
+/// Parses `N` as a single location or `N..M` as the half-open range of locations
+fn parse_location_range(s: &str) -> Result<RangeInterval, String> {
+    let parse = |n: &str| {
+        n.parse::<u64>()
+            .map_err(|e| format!("Invalid location \"{}\": {}", n, e))
+    };
+    let (start, end) = match s.split_once("..") {
+        Some((start, end)) => (parse(start)?, parse(end)?),
+        None => {
+            let location = parse(s)?;
+            (
+                location,
+                location.checked_add(1).ok_or("Location overflows")?,
+            )
         }
+    };
+    if start >= end {
+        return Err(format!("Empty location range \"{}\"", s));
     }
+    Ok(RangeInterval::new(start, end))
+}
+
+/// Usage: `day-05 [individual|ranges] [--staged|--inverse] [--origin <N|N..M>]...`,
+/// answering both parts when no interpretation is given
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut interpretations = vec![];
+    let mut evaluation = Evaluation::Compiled;
+    let mut origins = vec![];
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--staged" => evaluation = Evaluation::Staged,
+            "--inverse" => evaluation = Evaluation::Inverse,
+            "--origin" => origins.push(parse_location_range(
+                &args
+                    .next()
+                    .ok_or("Expected a location range after --origin")?,
+            )?),
+            _ => interpretations.push(arg.parse()?),
+        }
+    }
+    if interpretations.is_empty() {
+        interpretations = vec![SeedInterpretation::Individual, SeedInterpretation::Ranges];
+    }
+    Ok(Args {
+        interpretations,
+        evaluation,
+        origins,
+    })
 }
 
 fn main() {
+    let args = parse_args(std::env::args().skip(1)).unwrap_or_else(|e| panic!("{}", e));
     let start_time = SystemTime::now();
 
     println!("Parsing input...");
@@ -470,17 +1004,55 @@ fn main() {
         .lines()
         .map(|s| s.expect("Failed to read line"));
 
-    let (seed_numbers, mappings) =
-        parse_input_into_mappings(&mut input).expect("Failed to parse input");
+    let (seeds, mappings) = parse_input_into_mappings(&mut input).expect("Failed to parse input");
+
+    let compiled_mappings = if args.evaluation == Evaluation::Compiled {
+        println!("Compiling mappings...");
+        Some(mappings.compile())
+    } else {
+        None
+    };
 
     println!("Calculating final mappings...");
-    let final_mapping = mappings.get_lowest_final_mapping(&seed_numbers);
-    let end_time = SystemTime::now();
-    let calc_duration = end_time.duration_since(start_time).unwrap();
+    for interpretation in args.interpretations {
+        let final_mapping =
+            seeds
+                .intervals(interpretation)
+                .and_then(|seed_ranges| match &compiled_mappings {
+                    Some(compiled_mappings) => {
+                        compiled_mappings.get_lowest_final_mapping(&seed_ranges)
+                    }
+                    None if args.evaluation == Evaluation::Inverse => {
+                        mappings.get_lowest_reachable_location(&seed_ranges)
+                    }
+                    None => mappings.get_lowest_final_mapping(&seed_ranges),
+                });
+        match final_mapping {
+            Ok(final_mapping) => println!(
+                "{}: lowest final mapping: {}",
+                interpretation.label(),
+                final_mapping
+            ),
+            Err(e) => println!("{}: {}", interpretation.label(), e),
+        }
+    }
+    for locations in args.origins.iter() {
+        let seed_ranges: Vec<String> = mappings
+            .seeds_leading_to(locations)
+            .iter()
+            .map(|range| format!("{}..{}", range.start(), range.end()))
+            .collect();
+        println!(
+            "Locations {}..{} come from seeds {}",
+            locations.start(),
+            locations.end(),
+            seed_ranges.join(", ")
+        );
+    }
+    let calc_duration = SystemTime::now().duration_since(start_time).unwrap();
 
     println!(
-        "Lowest final mapping: {:?}; found in {:?}s and {:}ms",
-        final_mapping.iter().min().unwrap(),
+        "Found in {:?}s and {:}ms",
         calc_duration.as_secs(),
         calc_duration.subsec_millis()
     );
@@ -488,9 +1060,13 @@ fn main() {
 
 #[cfg(test)]
 mod test {
-    use std::time::SystemTime;
-
-    use crate::day5::{parse::parse_input_into_mappings, types::RangeInterval};
+    use crate::{
+        day5::{
+            parse::parse_input_into_mappings,
+            types::{RangeInterval, SeedInterpretation},
+        },
+        parse_location_range,
+    };
 
     const TEST_INPUT: &str = r#"seeds: 79 14 55 13
 
@@ -529,24 +1105,139 @@ humidity-to-location map:
     #[test]
     fn test_parses_mappings() {
         let line_iter = TEST_INPUT.split("\n").map(|s| s.to_string());
-        let (seed_ranges, mapping_pipeline) = parse_input_into_mappings(line_iter).unwrap();
+        let (seeds, mapping_pipeline) = parse_input_into_mappings(line_iter).unwrap();
+
+        assert_eq!(
+            seeds.intervals(SeedInterpretation::Ranges).unwrap(),
+            vec![RangeInterval::new(79, 93), RangeInterval::new(55, 68)]
+        );
+        assert_eq!(
+            seeds.intervals(SeedInterpretation::Individual).unwrap(),
+            vec![
+                RangeInterval::new(79, 80),
+                RangeInterval::new(14, 15),
+                RangeInterval::new(55, 56),
+                RangeInterval::new(13, 14)
+            ]
+        );
 
+        // Seed 79 goes through soil 81, fertilizer 81, water 81, light 74,
+        // temperature 78, humidity 78 and ends at location 82
         assert_eq!(
-            *seed_ranges,
-            vec![RangeInterval::new(79, 14), RangeInterval::new(55, 13)]
+            mapping_pipeline.get_lowest_final_mapping(&[RangeInterval::new(79, 80)]),
+            Ok(82)
         );
     }
 
     #[test]
     fn test_calculates_final_mappings() {
         let line_iter = TEST_INPUT.split("\n").map(|s| s.to_string());
-        let (seed_numbers, mapping_pipeline) = parse_input_into_mappings(line_iter).unwrap();
+        let (seeds, mapping_pipeline) = parse_input_into_mappings(line_iter).unwrap();
+
+        let lowest_for = |interpretation| {
+            mapping_pipeline
+                .get_lowest_final_mapping(&seeds.intervals(interpretation).unwrap())
+                .unwrap()
+        };
+        assert_eq!(lowest_for(SeedInterpretation::Individual), 35);
+        assert_eq!(lowest_for(SeedInterpretation::Ranges), 46);
+
+        let compiled_pipeline = mapping_pipeline.compile();
+        assert_eq!(
+            [79, 14, 55, 13].map(|seed| {
+                compiled_pipeline.get_lowest_final_mapping(&[RangeInterval::new(seed, seed + 1)])
+            }),
+            [Ok(82), Ok(43), Ok(86), Ok(35)]
+        );
+        assert_eq!(
+            compiled_pipeline
+                .get_lowest_final_mapping(&seeds.intervals(SeedInterpretation::Ranges).unwrap()),
+            Ok(46)
+        );
 
-        println!("Seed numbers: {:?}", seed_numbers);
+        for interpretation in [SeedInterpretation::Individual, SeedInterpretation::Ranges] {
+            assert_eq!(
+                mapping_pipeline
+                    .get_lowest_reachable_location(&seeds.intervals(interpretation).unwrap()),
+                Ok(lowest_for(interpretation))
+            );
+        }
+        // Seed 82 is where location 46 comes from in the seed ranges
+        assert!(mapping_pipeline
+            .seeds_leading_to(&RangeInterval::new(46, 47))
+            .contains(&RangeInterval::new(82, 83)));
+    }
 
-        let final_mapping = mapping_pipeline
-            .get_lowest_final_mapping(&seed_numbers)
-            .unwrap();
-        assert_eq!(final_mapping, 46);
+    #[test]
+    fn test_odd_seed_count_only_works_individually() {
+        let input = TEST_INPUT.replacen("seeds: 79 14 55 13", "seeds: 79 14 55", 1);
+        let (seeds, mapping_pipeline) =
+            parse_input_into_mappings(input.split("\n").map(|s| s.to_string())).unwrap();
+
+        assert_eq!(
+            seeds.intervals(SeedInterpretation::Ranges),
+            Err("LINE 0: Expected even number of seed numbers, got 3".to_string())
+        );
+        // Seed 13 was the one ending at location 35
+        let seed_ranges = seeds.intervals(SeedInterpretation::Individual).unwrap();
+        assert_eq!(
+            mapping_pipeline.get_lowest_final_mapping(&seed_ranges),
+            Ok(43)
+        );
+        assert_eq!("ranges".parse(), Ok(SeedInterpretation::Ranges));
+        assert!("pairs".parse::<SeedInterpretation>().is_err());
+    }
+
+    #[test]
+    fn test_empty_seed_ranges_have_no_lowest_location() {
+        // Compiled, staged and inverse evaluation of the seed ranges
+        let lowest_in_every_mode = |seeds_line: &str| {
+            let input = TEST_INPUT.replacen("seeds: 79 14 55 13", seeds_line, 1);
+            let (seeds, mapping_pipeline) =
+                parse_input_into_mappings(input.split("\n").map(|s| s.to_string())).unwrap();
+            let seed_ranges = seeds.intervals(SeedInterpretation::Ranges).unwrap();
+            [
+                mapping_pipeline
+                    .compile()
+                    .get_lowest_final_mapping(&seed_ranges),
+                mapping_pipeline.get_lowest_final_mapping(&seed_ranges),
+                mapping_pipeline.get_lowest_reachable_location(&seed_ranges),
+            ]
+        };
+
+        let all_empty = Err("Seed ranges are all empty".to_string());
+        assert_eq!(
+            lowest_in_every_mode("seeds: 5 0"),
+            [all_empty.clone(), all_empty.clone(), all_empty]
+        );
+        // An empty range next to a non empty one is skipped
+        assert_eq!(
+            lowest_in_every_mode("seeds: 5 0 79 14"),
+            [Ok(46), Ok(46), Ok(46)]
+        );
+    }
+
+    #[test]
+    fn test_largest_seed_overflows() {
+        let input = TEST_INPUT.replacen("seeds: 79 14 55 13", "seeds: 18446744073709551615 1", 1);
+        let (seeds, _) =
+            parse_input_into_mappings(input.split("\n").map(|s| s.to_string())).unwrap();
+
+        assert_eq!(
+            seeds.intervals(SeedInterpretation::Individual),
+            Err("LINE 0: Seed 18446744073709551615 overflows".to_string())
+        );
+        assert_eq!(
+            seeds.intervals(SeedInterpretation::Ranges),
+            Err("LINE 0: Seed range start 18446744073709551615 + length 1 overflows".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parses_location_ranges() {
+        assert_eq!(parse_location_range("46"), Ok(RangeInterval::new(46, 47)));
+        assert_eq!(parse_location_range("0..10"), Ok(RangeInterval::new(0, 10)));
+        assert!(parse_location_range("10..10").is_err());
+        assert!(parse_location_range("x..10").is_err());
     }
 }
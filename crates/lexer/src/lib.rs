@@ -0,0 +1,408 @@
+//! Character level lexing shared by the puzzle parsers.
+//! Every character and token knows the line and column it came from, so parse
+//! errors can say where the input went wrong.
+
+use std::{
+    fmt::{self, Display},
+    ops::Deref,
+    str::FromStr,
+};
+
+/// Where something is in the input. Lines and columns both count from 0, and
+/// columns count chars rather than bytes.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+impl Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LINE {}: COL {}", self.line, self.column)
+    }
+}
+
+pub struct CharAt {
+    pub position: Position,
+    pub c: char,
+}
+impl From<CharAt> for char {
+    fn from(value: CharAt) -> Self {
+        value.c
+    }
+}
+impl Deref for CharAt {
+    type Target = char;
+    fn deref(&self) -> &Self::Target {
+        &self.c
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Token<'a> {
+    /// A run of ASCII digits
+    Number(&'a str),
+    /// A run of letters
+    Word(&'a str),
+    /// A run of whitespace other than newlines
+    Whitespace(&'a str),
+    Newline,
+    /// Any other single character
+    Symbol(char),
+}
+impl Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(digits) => write!(f, "number \"{}\"", digits),
+            Token::Word(word) => write!(f, "word \"{}\"", word),
+            Token::Whitespace(_) => write!(f, "whitespace"),
+            Token::Newline => write!(f, "newline"),
+            Token::Symbol(c) => write!(f, "\"{}\"", c),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TokenAt<'a> {
+    pub position: Position,
+    pub token: Token<'a>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LexError {
+    pub position: Position,
+    pub message: String,
+}
+impl LexError {
+    pub fn new(position: Position, message: &str) -> Self {
+        LexError {
+            position,
+            message: message.to_string(),
+        }
+    }
+}
+impl Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.position, self.message)
+    }
+}
+impl std::error::Error for LexError {}
+
+/// Reads an input a character or token at a time, keeping track of the
+/// position of the next character
+pub struct Lexer<'a> {
+    input: &'a str,
+    /// Byte offset of the next character in `input`
+    offset: usize,
+    position: Position,
+}
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self::starting_at_line(input, 0)
+    }
+
+    /// For when the input is one line out of a larger file
+    pub fn starting_at_line(input: &'a str, line: usize) -> Self {
+        Lexer {
+            input,
+            offset: 0,
+            position: Position { line, column: 0 },
+        }
+    }
+
+    /// Position of the next character, or just past the last one at the end
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    pub fn peek_char(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.offset == self.input.len()
+    }
+
+    pub fn is_at_end_of_line(&self) -> bool {
+        matches!(self.peek_char(), None | Some('\n'))
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.offset..]
+    }
+
+    /// Consumes the next character
+    pub fn next_char(&mut self) -> Option<CharAt> {
+        let c = self.peek_char()?;
+        let position = self.position;
+
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.column = 0;
+        } else {
+            self.position.column += 1;
+        }
+        Some(CharAt { position, c })
+    }
+
+    /// Consumes characters while they match, returning the text consumed
+    fn consume_while(&mut self, matches: impl Fn(char) -> bool) -> &'a str {
+        let start = self.offset;
+        while self.peek_char().is_some_and(&matches) {
+            self.next_char();
+        }
+        &self.input[start..self.offset]
+    }
+
+    pub fn peek_token(&self) -> Option<Token<'a>> {
+        let rest = self.rest();
+        let c = rest.chars().next()?;
+        let run = |matches: fn(char) -> bool| {
+            let len = rest.find(|c| !matches(c)).unwrap_or(rest.len());
+            &rest[..len]
+        };
+
+        Some(if c.is_ascii_digit() {
+            Token::Number(run(|c| c.is_ascii_digit()))
+        } else if c.is_alphabetic() {
+            Token::Word(run(char::is_alphabetic))
+        } else if c == '\n' {
+            Token::Newline
+        } else if c.is_whitespace() {
+            Token::Whitespace(run(|c| c.is_whitespace() && c != '\n'))
+        } else {
+            Token::Symbol(c)
+        })
+    }
+
+    /// Consumes the next token
+    pub fn next_token(&mut self) -> Option<TokenAt<'a>> {
+        let position = self.position;
+        let token = self.peek_token()?;
+        match token {
+            Token::Number(text) | Token::Word(text) | Token::Whitespace(text) => {
+                for _ in text.chars() {
+                    self.next_char();
+                }
+            }
+            Token::Newline | Token::Symbol(_) => {
+                self.next_char();
+            }
+        }
+        Some(TokenAt { position, token })
+    }
+
+    /// An error at the next character, saying what was expected and what was found
+    pub fn error_expected(&self, expected: &str) -> LexError {
+        let found = match self.peek_token() {
+            Some(token) => token.to_string(),
+            None => "end of line".to_string(),
+        };
+        LexError::new(
+            self.position,
+            &format!("Expected {}, got {}", expected, found),
+        )
+    }
+
+    pub fn try_consume_number<T>(&mut self) -> Result<T, LexError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let start = self.position;
+        let Some(Token::Number(_)) = self.peek_token() else {
+            return Err(self.error_expected("number"));
+        };
+
+        self.consume_while(|c| c.is_ascii_digit())
+            .parse::<T>()
+            .map_err(|e| LexError::new(start, &format!("Failed to parse number: {}", e)))
+    }
+
+    /// Consumes whitespace up to the end of the line, returning how many
+    /// characters were consumed
+    pub fn try_consume_whitespace(&mut self) -> Result<usize, LexError> {
+        let whitespace = self.consume_while(|c| c.is_whitespace() && c != '\n');
+        if whitespace.is_empty() {
+            return Err(self.error_expected("whitespace"));
+        }
+        Ok(whitespace.chars().count())
+    }
+
+    pub fn try_consume_char(&mut self, expected: char) -> Result<(), LexError> {
+        if self.peek_char() != Some(expected) {
+            return Err(self.error_expected(&format!("\"{}\"", expected)));
+        }
+        self.next_char();
+        Ok(())
+    }
+
+    pub fn try_consume_literal(&mut self, expected: &str) -> Result<(), LexError> {
+        if !self.rest().starts_with(expected) {
+            return Err(self.error_expected(&format!("\"{}\"", expected)));
+        }
+        for _ in expected.chars() {
+            self.next_char();
+        }
+        Ok(())
+    }
+
+    /// Consumes whitespace separated numbers, each preceded by whitespace, until
+    /// the end of the line or `end_of_list_char`, which is consumed too
+    pub fn try_consume_list_of_numbers<T>(
+        &mut self,
+        end_of_list_char: Option<char>,
+    ) -> Result<Vec<T>, LexError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let mut numbers = Vec::new();
+        while !self.is_at_end_of_line() {
+            self.try_consume_whitespace()?;
+
+            match (self.peek_char(), end_of_list_char) {
+                (Some(c), Some(end_of_list_char)) if c == end_of_list_char => {
+                    self.next_char();
+                    break;
+                }
+                (None | Some('\n'), _) => break,
+                _ => {}
+            }
+
+            numbers.push(self.try_consume_number()?);
+        }
+        Ok(numbers)
+    }
+
+    /// Errors unless the whole line has been consumed. A trailing newline is consumed.
+    pub fn expect_end_of_line(&mut self) -> Result<(), LexError> {
+        match self.peek_char() {
+            None => Ok(()),
+            Some('\n') => {
+                self.next_char();
+                Ok(())
+            }
+            Some(_) => Err(self.error_expected("end of line")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{LexError, Lexer, Position, Token, TokenAt};
+
+    #[test]
+    fn test_tracks_lines_and_columns() {
+        let mut lexer = Lexer::new("ab\nc");
+        let positions: Vec<(char, Position)> = std::iter::from_fn(|| lexer.next_char())
+            .map(|c| (c.c, c.position))
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                ('a', Position { line: 0, column: 0 }),
+                ('b', Position { line: 0, column: 1 }),
+                ('\n', Position { line: 0, column: 2 }),
+                ('c', Position { line: 1, column: 0 }),
+            ]
+        );
+        assert_eq!(lexer.position(), Position { line: 1, column: 1 });
+        assert!(lexer.is_at_end());
+
+        let mut lexer = Lexer::starting_at_line("é1", 7);
+        lexer.next_char();
+        assert_eq!(lexer.position(), Position { line: 7, column: 1 });
+    }
+
+    #[test]
+    fn test_reads_typed_tokens() {
+        let mut lexer = Lexer::new("Card 12:\t|x\n");
+        let tokens: Vec<TokenAt> = std::iter::from_fn(|| lexer.next_token()).collect();
+        let at = |line, column| Position { line, column };
+        assert_eq!(
+            tokens,
+            vec![
+                TokenAt {
+                    position: at(0, 0),
+                    token: Token::Word("Card")
+                },
+                TokenAt {
+                    position: at(0, 4),
+                    token: Token::Whitespace(" ")
+                },
+                TokenAt {
+                    position: at(0, 5),
+                    token: Token::Number("12")
+                },
+                TokenAt {
+                    position: at(0, 7),
+                    token: Token::Symbol(':')
+                },
+                TokenAt {
+                    position: at(0, 8),
+                    token: Token::Whitespace("\t")
+                },
+                TokenAt {
+                    position: at(0, 9),
+                    token: Token::Symbol('|')
+                },
+                TokenAt {
+                    position: at(0, 10),
+                    token: Token::Word("x")
+                },
+                TokenAt {
+                    position: at(0, 11),
+                    token: Token::Newline
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_consumes_lists_of_numbers() {
+        let mut lexer = Lexer::new(" 41  8 | 83 6\n 5");
+        assert_eq!(
+            lexer.try_consume_list_of_numbers::<u32>(Some('|')),
+            Ok(vec![41, 8])
+        );
+        assert_eq!(
+            lexer.try_consume_list_of_numbers::<u64>(Some('|')),
+            Ok(vec![83, 6])
+        );
+        assert_eq!(lexer.expect_end_of_line(), Ok(()));
+        assert_eq!(lexer.try_consume_list_of_numbers::<u8>(None), Ok(vec![5]));
+        assert!(lexer.is_at_end());
+    }
+
+    #[test]
+    fn test_errors_point_at_the_problem() {
+        let mut lexer = Lexer::starting_at_line(" 1 x", 3);
+        assert_eq!(
+            lexer.try_consume_list_of_numbers::<u32>(None),
+            Err(LexError::new(
+                Position { line: 3, column: 3 },
+                "Expected number, got word \"x\""
+            ))
+        );
+        assert_eq!(
+            lexer.try_consume_char(':').unwrap_err().to_string(),
+            "LINE 3: COL 3: Expected \":\", got word \"x\""
+        );
+
+        let mut lexer = Lexer::new("Card 300");
+        assert_eq!(
+            lexer.try_consume_literal("Cart").unwrap_err().message,
+            "Expected \"Cart\", got word \"Card\""
+        );
+        lexer.try_consume_literal("Card").unwrap();
+        assert_eq!(lexer.try_consume_whitespace(), Ok(1));
+        let error = lexer.try_consume_number::<u8>().unwrap_err();
+        assert_eq!(error.position, Position { line: 0, column: 5 });
+        assert!(error.message.starts_with("Failed to parse number"));
+        assert_eq!(
+            lexer.try_consume_whitespace().unwrap_err().message,
+            "Expected whitespace, got end of line"
+        );
+    }
+}